@@ -521,6 +521,7 @@ impl Font for CairoFont {}
 impl TextLayoutBuilder for CairoTextLayoutBuilder {
     type Out = CairoTextLayout;
 
+    // The toy text API lays out one line, so the max width, alignment and ellipsis are ignored by the defaults. ////
     fn build(self) -> Result<Self::Out, Error> {
         Ok(self.0)
     }
@@ -531,6 +532,29 @@ impl TextLayout for CairoTextLayout {
         self.font.text_extents(&self.text).x_advance
     }
 
+    // The toy text API lays out one line, so the line count, line metrics and height are the defaults. ////
+    fn ascent(&self) -> f64 { ////
+        self.font.extents().ascent ////
+    } ////
+
+    fn descent(&self) -> f64 { ////
+        self.font.extents().descent ////
+    } ////
+
+    fn line_height(&self) -> f64 { ////
+        self.font.extents().height ////
+    } ////
+
+    fn image_bounds(&self) -> Rect { ////
+        let extents = self.font.text_extents(&self.text); ////
+        let origin = Point::new(extents.x_bearing, extents.y_bearing); ////
+        Rect::from_origin_size(origin, (extents.width, extents.height)) ////
+    } ////
+
+    fn line_text(&self, line_number: usize) -> Option<&str> { ////
+        if line_number == 0 { Some(&self.text) } else { None } ////
+    } ////
+
     // first assume one line.
     // TODO do with lines
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
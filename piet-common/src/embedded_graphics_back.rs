@@ -7,7 +7,9 @@
 pub use piet_embedded_graphics::*;
 
 /// The `RenderContext` for the Embed backend, which is selected.
-pub type Piet = EmbedRenderContext;
+///
+/// `D` is the embedded-graphics draw target that renders the pixels.
+pub type Piet<'a, D> = EmbedRenderContext<'a, D>;
 
 /// The associated brush type for this backend.
 ///
@@ -42,6 +44,7 @@ pub type PietTextLayoutBuilder<'a> = EmbedTextLayoutBuilder;
 /// The associated image type for this backend.
 ///
 /// This type matches `RenderContext::Image`
+pub type Image = piet::ImageBuf<'static>; ////
 ////pub type Image = ImageSurface;
 
 /// A struct that can be used to create bitmap render contexts.
@@ -579,6 +579,10 @@ impl TextLayout for D2DTextLayout {
         self.layout.get_metrics().width() as f64
     }
 
+    fn line_text(&self, line_number: usize) -> Option<&str> { ////
+        if line_number == 0 { Some(&self.text) } else { None } ////
+    } ////
+
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
         // lossy from f64 to f32, but shouldn't have too much impact
         let htp = self.layout.hit_test_point(point.x as f32, point.y as f32);
@@ -1,21 +1,19 @@
 //! Batch the pixels to be rendered into Pixel Rows and Pixel Blocks (contiguous Pixel Rows).
 //! This enables the pixels to be rendered efficiently as Pixel Blocks, which may be transmitted in a single Non-Blocking SPI request.
+//! Any display driver that writes a window of pixels at a time may batch its pixels with `draw_blocks()`,
+//! by implementing `Drawing<Rgb565>` with a call to `draw_blocks()` and passing the driver to `EmbedRenderContext::new()`.
 use embedded_graphics::{
     prelude::*,
     pixelcolor::Rgb565, 
 };
-use embedded_hal::{
-    digital::v2::OutputPin,
-    blocking::{ 
-        spi,
-    },
-};
-use st7735_lcd::ST7735;
 
 /// Max number of pixels per Pixel Row
 type MaxRowSize = heapless::consts::U50;
 /// Max number of pixels per Pixel Block
 type MaxBlockSize = heapless::consts::U100;
+/// Max number of Pixel Blocks that may be extended by the next Pixel Rows.
+/// Sparse pixels, like the strokes of glyphs, are batched into a few narrow blocks at the same time.
+type MaxPendingBlocks = heapless::consts::U4;
 
 /// Consecutive color words for a Pixel Row
 type RowColors = heapless::Vec::<u16, MaxRowSize>;
@@ -28,11 +26,11 @@ pub struct RowIterator<P: Iterator<Item = Pixel<Rgb565>>> {
     /// Pixels to be batched into rows
     pixels:      P,
     /// Start column number
-    x_left:      u8,
+    x_left:      u16,
     /// End column number
-    x_right:     u8,
+    x_right:     u16,
     /// Row number
-    y:           u8,
+    y:           u16,
     /// List of pixel colours for the entire row
     colors:      RowColors,
     /// True if this is the first pixel for the row
@@ -44,54 +42,47 @@ pub struct RowIterator<P: Iterator<Item = Pixel<Rgb565>>> {
 pub struct BlockIterator<R: Iterator<Item = PixelRow>> {
     /// Pixel Rows to be batched into blocks
     rows:        R,
-    /// Start column number
-    x_left:      u8,
-    /// End column number
-    x_right:     u8,
-    /// Start row number
-    y_top:       u8,
-    /// End row number
-    y_bottom:    u8,
-    /// List of pixel colours for the entire block, row by row
-    colors:      BlockColors,
-    /// True if this is the first row for the block
-    first_row:   bool,
+    /// Pixel Blocks that may be extended by the next Pixel Rows
+    pending:     heapless::Vec<PixelBlock, MaxPendingBlocks>,
+    /// Pixel Row to be batched after an overlapping block has been returned
+    next_row:    Option<PixelRow>,
 }
 
 /// A row of contiguous pixels
+#[derive(Debug, Clone)]
 pub struct PixelRow {
     /// Start column number
-    pub x_left:  u8,
+    pub x_left:  u16,
     /// End column number
-    pub x_right: u8,
+    pub x_right: u16,
     /// Row number
-    pub y:       u8,
+    pub y:       u16,
     /// List of pixel colours for the entire row
     pub colors:  RowColors,
 }
 
 /// A block of contiguous pixel rows with the same start and end column number
+#[derive(Debug, Clone)]
 pub struct PixelBlock {
     /// Start column number
-    pub x_left:   u8,
+    pub x_left:   u16,
     /// End column number
-    pub x_right:  u8,
+    pub x_right:  u16,
     /// Start row number
-    pub y_top:    u8,
+    pub y_top:    u16,
     /// End row number
-    pub y_bottom: u8,
+    pub y_bottom: u16,
     /// List of pixel colours for the entire block, row by row
     pub colors:   BlockColors,
 }
 
 /// Draw the pixels in the item as Pixel Blocks of contiguous Pixel Rows. The pixels are grouped by row then by block.
-#[allow(dead_code)]
-pub fn draw_blocks<SPI, DC, RST, T>(display: &mut ST7735<SPI, DC, RST>, item_pixels: T) -> Result<(),()>
+/// `set_pixels` is called with the window of each block (start column, start row, end column, end row, inclusive)
+/// and the pixel colours of the block, row by row, like `ST7735::set_pixels()`.
+pub fn draw_blocks<T, F, E>(item_pixels: T, mut set_pixels: F) -> Result<(), E>
 where
-    SPI: spi::Write<u8>,
-    DC: OutputPin,
-    RST: OutputPin,
-    T: IntoIterator<Item = Pixel<Rgb565>>, {
+    T: IntoIterator<Item = Pixel<Rgb565>>,
+    F: FnMut(u16, u16, u16, u16, &[u16]) -> Result<(), E>, {
     //  Get the pixels for the item to be rendered.
     let pixels = item_pixels.into_iter();
     //  Batch the pixels into Pixel Rows.
@@ -101,12 +92,7 @@ where
     //  For each Pixel Block...
     for PixelBlock { x_left, x_right, y_top, y_bottom, colors, .. } in blocks {
         //  Render the Pixel Block.
-        display.set_pixels(
-            x_left as u16, 
-            y_top as u16,
-            x_right as u16,
-            y_bottom as u16,
-            colors) ? ;
+        set_pixels(x_left, y_top, x_right, y_bottom, &colors) ? ;
 
         //  Dump out the Pixel Blocks for the square in test_display()
         /* if x_left >= 60 && x_left <= 150 && x_right >= 60 && x_right <= 150 && y_top >= 60 && y_top <= 150 && y_bottom >= 60 && y_bottom <= 150 {
@@ -119,7 +105,7 @@ where
 
 /// Batch the pixels into Pixel Rows, which are contiguous pixels on the same row.
 /// P can be any Pixel Iterator (e.g. a rectangle).
-fn to_rows<P>(pixels: P) -> RowIterator<P>
+pub fn to_rows<P>(pixels: P) -> RowIterator<P>
 where
     P: Iterator<Item = Pixel<Rgb565>>, {
     RowIterator::<P> {
@@ -134,20 +120,33 @@ where
 
 /// Batch the Pixel Rows into Pixel Blocks, which are contiguous Pixel Rows with the same start and end column number
 /// R can be any Pixel Row Iterator.
-fn to_blocks<R>(rows: R) -> BlockIterator<R>
+pub fn to_blocks<R>(rows: R) -> BlockIterator<R>
 where
     R: Iterator<Item = PixelRow>, {
     BlockIterator::<R> {
         rows,
-        x_left: 0,
-        x_right: 0,
-        y_top: 0,
-        y_bottom: 0,
-        colors: BlockColors::new(),
-        first_row: true,
+        pending: heapless::Vec::new(),
+        next_row: None,
     }
 }    
 
+impl PixelBlock {
+    /// Return true if the Pixel Row is just below the block, with the same start and end column number,
+    /// and the block has room for the row
+    fn is_extended_by(&self, row: &PixelRow) -> bool {
+        row.y as u32 == self.y_bottom as u32 + 1
+            && row.x_left == self.x_left
+            && row.x_right == self.x_right
+            && self.colors.len() + row.colors.len() <= self.colors.capacity()
+    }
+
+    /// Return true if the Pixel Row overlaps any pixel in the block
+    fn overlaps(&self, row: &PixelRow) -> bool {
+        row.y >= self.y_top && row.y <= self.y_bottom
+            && row.x_left <= self.x_right && row.x_right >= self.x_left
+    }
+}
+
 /// Implement the Iterator for Pixel Rows.
 /// P can be any Pixel Iterator (e.g. a rectangle).
 impl<P: Iterator<Item = Pixel<Rgb565>>> Iterator for RowIterator<P> {
@@ -177,8 +176,8 @@ impl<P: Iterator<Item = Pixel<Rgb565>>> Iterator for RowIterator<P> {
                     return Some(row);
                 }
                 Some(Pixel(coord, color)) => {  //  If there is a pixel...
-                    let x = coord.0 as u8;
-                    let y = coord.1 as u8;
+                    let x = coord.0 as u16;
+                    let y = coord.1 as u16;
                     let color = color.0;
                     //  Save the first pixel as the row start and handle next pixel.
                     if self.first_pixel {
@@ -192,7 +191,7 @@ impl<P: Iterator<Item = Pixel<Rgb565>>> Iterator for RowIterator<P> {
                         continue;
                     }
                     //  If this pixel is adjacent to the previous pixel, add to the row.
-                    if x == self.x_right + 1 && y == self.y {
+                    if x as u32 == self.x_right as u32 + 1 && y == self.y {
                         if self.colors.push(color).is_ok() {
                             //  Don't add pixel if too many pixels in the row.
                             self.x_right = x;
@@ -227,66 +226,42 @@ impl<R: Iterator<Item = PixelRow>> Iterator for BlockIterator<R> {
 
     /// Return the next Pixel Block of contiguous Pixel Rows with the same start and end column number
     fn next(&mut self) -> Option<Self::Item> {
-        //  Loop over all Pixel Rows until we have composed a Pixel Block, or we have run out of Pixel Rows.
+        //  Loop over all Pixel Rows until we have completed a Pixel Block, or we have run out of Pixel Rows.
         loop {
             //  Get the next Pixel Row.
-            let next_row = self.rows.next();
-            match next_row {
-                None => {  //  If no more Pixel Rows...
-                    if self.first_row {
-                        return None;  //  No rows to group
-                    }                    
-                    //  Else return previous rows as block.
-                    let row = PixelBlock {
-                        x_left: self.x_left,
-                        x_right: self.x_right,
-                        y_top: self.y_top,
-                        y_bottom: self.y_bottom,
-                        colors: self.colors.clone(),
-                    };
-                    self.colors.clear();
-                    self.first_row = true;
-                    return Some(row);
-                }
-                Some(PixelRow { x_left, x_right, y, colors, .. }) => {  //  If there is a Pixel Row...
-                    //  Save the first row as the block start and handle next block.
-                    if self.first_row {
-                        self.first_row = false;
-                        self.x_left = x_left;
-                        self.x_right = x_right;
-                        self.y_top = y;
-                        self.y_bottom = y;
-                        self.colors.clear();
-                        self.colors.extend_from_slice(&colors)
-                            .expect("never");
-                        continue;
-                    }
-                    //  If this row is adjacent to the previous row and same size, add to the block.
-                    if y == self.y_bottom + 1 && x_left == self.x_left && x_right == self.x_right {                        
-                        //  Don't add row if too many pixels in the block.
-                        if self.colors.extend_from_slice(&colors).is_ok() {
-                            self.y_bottom = y;
-                            continue;    
-                        }
-                    }
-                    //  Else return previous rows as block.
-                    let row = PixelBlock {
-                        x_left: self.x_left,
-                        x_right: self.x_right,
-                        y_top: self.y_top,
-                        y_bottom: self.y_bottom,
-                        colors: self.colors.clone(),
-                    };
-                    self.x_left = x_left;
-                    self.x_right = x_right;
-                    self.y_top = y;
-                    self.y_bottom = y;
-                    self.colors.clear();
-                    self.colors.extend_from_slice(&colors)
-                        .expect("never");
-                    return Some(row);
-                }
+            let row = match self.next_row.take().or_else(|| self.rows.next()) {
+                Some(row) => row,
+                None => return self.pending.pop(),  //  If no more Pixel Rows, return the pending blocks
+            };
+            //  If the row overlaps a pending block, return the block first, so that the pixels are drawn in order.
+            if let Some(i) = self.pending.iter().position(|block| block.overlaps(&row)) {
+                self.next_row = Some(row);
+                return Some(self.pending.swap_remove(i));
+            }
+            //  If this row is adjacent to a pending block and same size, add to the block.
+            if let Some(block) = self.pending.iter_mut().find(|block| block.is_extended_by(&row)) {
+                block.colors.extend_from_slice(&row.colors)
+                    .expect("never");
+                block.y_bottom = row.y;
+                continue;
+            }
+            //  Else start a new block with the row.
+            let PixelRow { x_left, x_right, y, colors } = row;
+            let mut block = PixelBlock { x_left, x_right, y_top: y, y_bottom: y, colors: BlockColors::new() };
+            block.colors.extend_from_slice(&colors)
+                .expect("never");
+            if self.pending.len() < self.pending.capacity() {
+                self.pending.push(block)
+                    .expect("never");
+                continue;
             }
+            //  If too many blocks are pending, return the block that ends at the highest row, which is least likely to be extended.
+            let i = self.pending.iter()
+                .enumerate()
+                .min_by_key(|(_, block)| block.y_bottom)
+                .map(|(i, _)| i)
+                .expect("never");
+            return Some(core::mem::replace(&mut self.pending[i], block));
         }
     }
 }
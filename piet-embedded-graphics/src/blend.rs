@@ -0,0 +1,84 @@
+//! Alpha blending of translucent pixels. Source-over compositing needs the colour of the destination pixel,
+//! which is kept in an optional `ShadowBuffer` because most display controllers are write-only.
+//! Without a shadow buffer, translucent pixels are rendered with the selected `AlphaFallback`.
+use embedded_graphics::pixelcolor::Rgb565;
+
+/// Ordered dithering thresholds for 4 x 4 pixels
+const BAYER_MATRIX: [[u8; 4]; 4] = [
+    [  0, 8,  2, 10 ],
+    [ 12, 4, 14,  6 ],
+    [  3, 11, 1,  9 ],
+    [ 15, 7, 13,  5 ],
+];
+
+/// How translucent pixels are rendered when there is no shadow buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaFallback {
+    /// Render translucent pixels as opaque. Only fully transparent pixels are skipped.
+    Opaque,
+    /// Render a fraction of the translucent pixels according to their alpha, in an ordered dither pattern
+    Dither,
+}
+
+impl AlphaFallback {
+    /// Return true if the pixel at (x,y) with the alpha should be rendered
+    pub fn is_visible(self, x: u32, y: u32, alpha: u8) -> bool {
+        match self {
+            AlphaFallback::Opaque => alpha > 0,
+            AlphaFallback::Dither => {
+                let threshold = BAYER_MATRIX[(y & 3) as usize][(x & 3) as usize] * 16 + 8;
+                alpha > threshold
+            }
+        }
+    }
+}
+
+/// Copy of the pixels rendered to the display, so that translucent pixels may be blended with
+/// the pixels underneath. For a 240 x 240 display, the buffer needs 57,600 words.
+pub struct ShadowBuffer<'b> {
+    /// Rgb565 colour of each pixel, row by row
+    pixels: &'b mut [u16],
+    /// Number of columns
+    width:  u32,
+    /// Number of rows
+    height: u32,
+}
+
+impl<'b> ShadowBuffer<'b> {
+    /// Create a shadow buffer for `width` by `height` pixels, stored in the buffer.
+    /// The buffer must contain at least `width * height` words. The pixels start as black.
+    pub fn new(buffer: &'b mut [u16], width: u32, height: u32) -> Self {
+        assert!(buffer.len() >= (width * height) as usize, "shadow buffer too small");
+        let pixels = &mut buffer[..(width * height) as usize];
+        for pixel in pixels.iter_mut() { *pixel = 0; }
+        ShadowBuffer { pixels, width, height }
+    }
+
+    /// Blend the colour with the alpha over the pixel at (x,y), and return the blended colour.
+    /// Pixels outside the buffer are returned unblended.
+    pub fn blend(&mut self, x: u32, y: u32, color: Rgb565, alpha: u8) -> Rgb565 {
+        if x >= self.width || y >= self.height { return color; }
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        let result = blend(color, Rgb565(*pixel), alpha);
+        *pixel = result.0;
+        result
+    }
+}
+
+/// Composite the source colour with the alpha over the destination colour
+pub fn blend(src: Rgb565, dst: Rgb565, alpha: u8) -> Rgb565 {
+    match alpha {
+        255 => src,
+        0   => dst,
+        _ => {
+            let a = alpha as u32;
+            let (s, d) = (src.0 as u32, dst.0 as u32);
+            //  Blend each channel: 5 bits red, 6 bits green, 5 bits blue
+            let channel = |shift: u32, mask: u32| {
+                let (s, d) = ((s >> shift) & mask, (d >> shift) & mask);
+                ((s * a + d * (255 - a) + 127) / 255) << shift
+            };
+            Rgb565((channel(11, 0x1f) | channel(5, 0x3f) | channel(0, 0x1f)) as u16)
+        }
+    }
+}
@@ -1,20 +1,51 @@
 use piet::kurbo::Rect;
 use piet::{
+    FixedLinearGradient, FixedRadialGradient,
     IntoBrush, 
 };
-use crate::context;
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    Drawing,
+};
+use heapless::ArrayLength;
+use crate::{ context, gradient, image };
 
 #[derive(Clone)]
 pub enum Brush {
     Solid(u32),
-    ////Linear(embedded_graphics::LinearGradient),
-    ////Radial(embedded_graphics::RadialGradient),
+    Linear(FixedLinearGradient),
+    Radial(FixedRadialGradient),
+}
+
+/// Source pattern for rendering a brush, in pixel coordinates
+#[derive(Clone)]
+pub enum Pattern {
+    /// Solid colour with alpha
+    Solid(Rgb565, u8),
+    /// Linear gradient
+    Linear(gradient::LinearShader),
+    /// Radial gradient
+    Radial(gradient::RadialShader),
+    /// Bitmap image
+    Image(image::ImageShader),
+}
+
+impl Pattern {
+    /// Return the colour and alpha of the pattern at pixel (x,y)
+    pub fn color_at(&self, x: i32, y: i32) -> (Rgb565, u8) {
+        match self {
+            Pattern::Solid(color, alpha) => (*color, *alpha),
+            Pattern::Linear(linear)      => linear.color_at(x, y),
+            Pattern::Radial(radial)      => radial.color_at(x, y),
+            Pattern::Image(image)        => image.color_at(x, y),
+        }
+    }
 }
 
-impl<'a> IntoBrush<context::EmbedRenderContext> for Brush {
+impl<'a, D: Drawing<Rgb565>, N: ArrayLength<u8>> IntoBrush<context::EmbedRenderContext<'a, D, N>> for Brush {
     fn make_brush<'b>(
         &'b self,
-        _piet: &mut context::EmbedRenderContext,
+        _piet: &mut context::EmbedRenderContext<'a, D, N>,
         _bbox: impl FnOnce() -> Rect,
     ) -> Brush {
         self.clone()
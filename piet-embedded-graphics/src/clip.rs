@@ -0,0 +1,120 @@
+//! Clip regions for the render context. The clip region is always intersected with a rectangle in pixel
+//! coordinates, which is applied to the spans of pixels as they are rasterized. Arbitrary shapes are clipped
+//! with an optional `ClipMask`, which keeps a clip count for every pixel of the display.
+use piet::kurbo::Rect;
+use crate::raster::Span;
+
+/// Rectangle of pixels that may be drawn, from (x0,y0) to (x1,y1) (exclusive)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipRect {
+    /// First column
+    pub x0: i32,
+    /// First row
+    pub y0: i32,
+    /// Last column (exclusive)
+    pub x1: i32,
+    /// Last row (exclusive)
+    pub y1: i32,
+}
+
+impl ClipRect {
+    /// Create a clip rectangle that covers `width` by `height` pixels
+    pub fn new(width: u16, height: u16) -> Self {
+        ClipRect { x0: 0, y0: 0, x1: width as i32, y1: height as i32 }
+    }
+
+    /// Return true if the pixel at column `x` and row `y` is inside the rectangle
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x0 && x < self.x1 && y >= self.y0 && y < self.y1
+    }
+
+    /// Return true if the rectangle contains no pixels
+    pub fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+
+    /// Return the number of pixels in the rectangle
+    pub fn area(&self) -> i32 {
+        if self.is_empty() { 0 }
+        else { (self.x1 - self.x0) * (self.y1 - self.y0) }
+    }
+
+    /// Return the smallest rectangle that contains the rectangle and the span
+    pub fn union_span(&self, span: &Span) -> Self {
+        if self.is_empty() {
+            return ClipRect { x0: span.x0, y0: span.y, x1: span.x1, y1: span.y + 1 };
+        }
+        ClipRect {
+            x0: self.x0.min(span.x0),
+            y0: self.y0.min(span.y),
+            x1: self.x1.max(span.x1),
+            y1: self.y1.max(span.y + 1),
+        }
+    }
+
+    /// Return the rectangle as bounds for the rasterizer, which keeps the pixels whose centres are inside
+    pub fn to_rect(&self) -> Rect {
+        if self.is_empty() { return Rect::ZERO; }
+        Rect::new(self.x0 as f64, self.y0 as f64, self.x1 as f64, self.y1 as f64)
+    }
+}
+
+/// Mask for clipping to arbitrary shapes, with one byte per pixel. Each byte counts the clip shapes
+/// that contain the pixel, so that clips may be nested and undone by `restore()` without saving the mask.
+/// For a 240 x 240 display, the buffer needs 57,600 bytes.
+pub struct ClipMask<'m> {
+    /// Clip count for each pixel, row by row
+    counts: &'m mut [u8],
+    /// Number of columns
+    width:  u32,
+    /// Number of rows
+    height: u32,
+}
+
+impl<'m> ClipMask<'m> {
+    /// Create a clip mask for `width` by `height` pixels, stored in the buffer.
+    /// The buffer must contain at least `width * height` bytes.
+    pub fn new(buffer: &'m mut [u8], width: u32, height: u32) -> Self {
+        assert!(buffer.len() >= (width * height) as usize, "clip mask too small");
+        let counts = &mut buffer[..(width * height) as usize];
+        for count in counts.iter_mut() { *count = 0; }
+        ClipMask { counts, width, height }
+    }
+
+    /// Return true if the pixel is inside all `depth` clip shapes
+    pub fn is_visible(&self, x: u32, y: u32, depth: u8) -> bool {
+        if depth == 0 { return true; }
+        if x >= self.width || y >= self.height { return false; }
+        self.counts[(y * self.width + x) as usize] >= depth
+    }
+
+    /// Clip to the spans of a shape: pixels inside the `depth` previous clip shapes and inside the spans
+    /// are now inside `depth + 1` clip shapes
+    pub fn clip(&mut self, spans: impl Iterator<Item = Span>, depth: u8) {
+        for span in spans {
+            if span.y < 0 || span.y >= self.height as i32 { continue; }
+            let x0 = span.x0.max(0) as u32;
+            let x1 = span.x1.min(self.width as i32).max(0) as u32;
+            let row = (span.y as u32 * self.width) as usize;
+            for count in self.counts[row + x0 as usize .. row + x1.max(x0) as usize].iter_mut() {
+                if *count == depth { *count = depth + 1; }
+            }
+        }
+    }
+
+    /// Undo the clip shapes after the first `depth` clip shapes. Only the pixels inside `bounds` are updated,
+    /// so `bounds` must contain the clip shapes, like the clip rectangle when the first `depth` shapes were applied.
+    pub fn restore(&mut self, depth: u8, bounds: ClipRect) {
+        let x0 = bounds.x0.max(0).min(self.width as i32) as usize;
+        let x1 = bounds.x1.max(0).min(self.width as i32) as usize;
+        let y0 = bounds.y0.max(0).min(self.height as i32) as usize;
+        let y1 = bounds.y1.max(0).min(self.height as i32) as usize;
+        if x0 >= x1 { return; }
+        for y in y0..y1 {
+            let row = y * self.width as usize;
+            for count in self.counts[row + x0 .. row + x1].iter_mut() {
+                if *count > depth { *count = depth; }
+            }
+        }
+    }
+}
@@ -1,72 +1,193 @@
-use piet::kurbo::{Affine, PathEl, Point, Rect, Shape};
+use piet::kurbo::{Affine, Point, Rect, Shape, Vec2};
 use piet::{
     ////new_error, 
     Color, Error, 
     ////ErrorKind, 
+    FixedGradient,
+    ImageBuf, ImageFormat, InterpolationMode,
     IntoBrush, 
-    RenderContext, StrokeStyle,
+    RenderContext, StrokeStyle, TextLayout,
 };
 use embedded_graphics::{
     prelude::*,
-    fonts::Font as EFont,
-    primitives::{
-        Line,
-        Rectangle,
-    },
     pixelcolor::Rgb565, 
+    Drawing,
 };
-use mynewt::sys::console;
-use crate::{ brush, text };
-use super::display;
+use heapless::ArrayLength;
+use crate::{ blend::{ AlphaFallback, ShadowBuffer }, brush::{ self, Pattern }, clip::{ ClipMask, ClipRect }, gradient, image, raster, stroke, text };
 
+/// Default size of the draw target, set by `with_size()`
 const DISPLAY_WIDTH:  u16 = 240;  //  For PineTime Display
 const DISPLAY_HEIGHT: u16 = 240;  //  For PineTime Display
 
-static mut EMBED_TEXT: text::EmbedText = text::EmbedText;
+/// Number of rows to be drawn in each block when clearing the screen
+const CLEAR_BLOCK_ROWS: i32 = 8;
 
-/// Maximum number of transforms supported
-type MaxTransforms = heapless::consts::U10;
-/// Stack of transforms to be applied to the current render
-static mut TRANSFORM_STACK: heapless::Vec<Point, MaxTransforms> = 
-    heapless::Vec(heapless::i::Vec::new());
+/// Colour of the text drawn by embedded-graphics, to be replaced by the brush pattern
+const FOREGROUND: Rgb565 = Rgb565(0xffff);
 
-fn get_transform_stack() -> Coord {
-    let mut result = Point::ZERO;
-    unsafe {
-        for p in &mut TRANSFORM_STACK {
-            result.x += p.x;
-            result.y += p.y;
-        }    
-    }
-    Coord::new(result.x as i32, result.y as i32)
+/// Max distance in pixels between a curve and the line segments that approximate the curve
+const FLATTEN_TOLERANCE: f64 = 0.25;
+
+/// Maximum number of saved states supported
+type MaxStates = heapless::consts::U10;
+
+/// Context state that is saved by `save()` and restored by `restore()`
+#[derive(Clone, Copy)]
+struct State {
+    /// Transform from user coordinates to pixel coordinates
+    transform:  Affine,
+    /// Pixels that may be drawn, intersected with every clip shape
+    clip:       ClipRect,
+    /// Number of clip shapes applied to the clip mask
+    clip_depth: u8,
+}
+
+/// Return the largest factor by which the transform scales distances, approximately
+fn transform_scale(transform: Affine) -> f64 {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    libm::sqrt(a * a + b * b).max(libm::sqrt(c * c + d * d))
+}
+
+/// Return the tolerance in user coordinates for flattening curves, such that the error in pixels is within `FLATTEN_TOLERANCE`
+fn flatten_tolerance(transform: Affine) -> f64 {
+    let scale = transform_scale(transform);
+    if scale > 0. { FLATTEN_TOLERANCE / scale }
+    else { FLATTEN_TOLERANCE }
 }
 
-pub struct EmbedRenderContext {
-    // display: &'a mut Display,
-    // text: &'a mut text::EmbedText,
-    // phantom: PhantomData<u32 + 'a>,
+/// Return true if the transform only translates
+fn is_translation(transform: Affine) -> bool {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    a == 1. && b == 0. && c == 0. && d == 1.
 }
 
-/*
-impl<'a> EmbedRenderContext<'a> {
-    /// Create a new embedded-graphics back-end.
+/// Render context for embedded-graphics. Renders the piet graphics to an embedded-graphics draw target,
+/// like a display driver or a framebuffer.
+pub struct EmbedRenderContext<'a, D: Drawing<Rgb565>, N: ArrayLength<u8> = text::DefaultTextCapacity> {
+    /// Draw target that renders the pixels
+    display:   &'a mut D,
+    /// Text factory for fonts and text layouts of up to `N` bytes
+    text:      text::EmbedText<N>,
+    /// Current context state
+    state:     State,
+    /// Context states saved by `save()`
+    saved:     heapless::Vec<State, MaxStates>,
+    /// Mask for clipping to shapes that are not rectangles
+    mask:      Option<ClipMask<'a>>,
+    /// Copy of the rendered pixels for blending translucent pixels
+    shadow:    Option<ShadowBuffer<'a>>,
+    /// How translucent pixels are rendered without a shadow buffer
+    fallback:  AlphaFallback,
+    /// True if fills and strokes are anti-aliased
+    antialias: bool,
+}
+
+impl<'a, D: Drawing<Rgb565>> EmbedRenderContext<'a, D> {
+    /// Create a new embedded-graphics back-end that renders to the draw target.
+    /// The draw target is 240 x 240 pixels (the PineTime display) unless set by `with_size()`.
     ///
     /// At the moment, it uses the "toy text API" for text layout, but when
     /// we change to a more sophisticated text layout approach, we'll probably
     /// need a factory for that as an additional argument.
-    pub fn new() -> EmbedRenderContext {
+    pub fn new(display: &'a mut D) -> EmbedRenderContext<'a, D> {
         EmbedRenderContext {
-            // display: unsafe { &mut DISPLAY },
-            // text: unsafe { &mut EMBED_TEXT },
+            display,
+            text:      text::EmbedText::new(),
+            state:     State {
+                transform:  Affine::default(),
+                clip:       ClipRect::new(DISPLAY_WIDTH, DISPLAY_HEIGHT),
+                clip_depth: 0,
+            },
+            saved:     heapless::Vec::new(),
+            mask:      None,
+            shadow:    None,
+            fallback:  AlphaFallback::Dither,
+            antialias: false,
         }
     }
 }
-*/
 
-impl RenderContext for EmbedRenderContext {
+impl<'a, D: Drawing<Rgb565>, N: ArrayLength<u8>> EmbedRenderContext<'a, D, N> {
+    /// Lay out text of up to `M` bytes, like `with_text_capacity::<U256>()`. The default is `DefaultTextCapacity`.
+    pub fn with_text_capacity<M: ArrayLength<u8>>(self) -> EmbedRenderContext<'a, D, M> {
+        EmbedRenderContext {
+            display:   self.display,
+            text:      self.text.resize(),
+            state:     self.state,
+            saved:     self.saved,
+            mask:      self.mask,
+            shadow:    self.shadow,
+            fallback:  self.fallback,
+            antialias: self.antialias,
+        }
+    }
+
+    /// Set the size of the draw target in pixels. Pixels outside are not drawn. The default is 240 x 240.
+    /// This resets the clip region, so it should be set before drawing.
+    pub fn with_size(mut self, width: u16, height: u16) -> Self {
+        self.state.clip = ClipRect::new(width, height);
+        self
+    }
+
+    /// Clip to arbitrary shapes with the clip mask. Without a clip mask, shapes are clipped to their bounding box.
+    pub fn with_clip_mask(mut self, mask: ClipMask<'a>) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Blend translucent pixels with the pixels in the shadow buffer, which keeps a copy of the rendered pixels.
+    /// Without a shadow buffer, translucent pixels are rendered with the alpha fallback.
+    pub fn with_shadow_buffer(mut self, shadow: ShadowBuffer<'a>) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    /// Set how translucent pixels are rendered without a shadow buffer. The default is `AlphaFallback::Dither`.
+    pub fn with_alpha_fallback(mut self, fallback: AlphaFallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Anti-alias the edges of fills and strokes by blending the coverage of the edge pixels.
+    /// The edges are blended with the shadow buffer, or rendered with the alpha fallback if there is no shadow buffer.
+    pub fn with_antialias(mut self, antialias: bool) -> Self {
+        self.antialias = antialias;
+        self
+    }
+
+    /// Draw the text layout like `draw_text()`, after filling the character cells of each line with the background brush.
+    /// `draw_text()` draws only the glyphs, so the pixels behind the text are unchanged.
+    pub fn draw_text_with_background(
+        &mut self,
+        layout: &text::EmbedTextLayout<N>,
+        pos: impl Into<Point>,
+        brush: &impl IntoBrush<Self>,
+        background: &impl IntoBrush<Self>,
+    ) {
+        let pos = pos.into();
+        let background = background.make_brush(self, || layout.image_bounds() + pos.to_vec2());
+
+        //  Get fill pattern
+        let fill = self.convert_brush(&background);
+
+        //  Fill the cells of each line, then draw the glyphs
+        let transform = self.state.transform * Affine::translate(pos.to_vec2());
+        for line in layout.lines() {
+            let cells = layout.line_bounds(&line);
+            if cells.width() == 0. { continue; }
+            let outline = raster::ShapeOutline::new(&cells, transform, flatten_tolerance(transform));
+            self.fill_outline(&outline, raster::FillRule::NonZero, &fill);
+        }
+        self.draw_text(layout, pos, brush);
+    }
+}
+
+impl<'a, D: Drawing<Rgb565>, N: ArrayLength<u8>> RenderContext for EmbedRenderContext<'a, D, N> {
     type Brush = brush::Brush;
-    type Text = text::EmbedText;
-    type TextLayout = text::EmbedTextLayout;
+    type Image = ImageBuf<'static>;
+    type Text = text::EmbedText<N>;
+    type TextLayout = text::EmbedTextLayout<N>;
 
     ////type Image = ImageSurface;
 
@@ -84,176 +205,65 @@ impl RenderContext for EmbedRenderContext {
     }
 
     fn clear(&mut self, color: Color) {
-        //  TODO: We can only clear the screen once. Second time will crash due to low stack space.
-        static mut FIRST_CLEAR: bool = true;
-        unsafe {
-            if !FIRST_CLEAR { return; }
-            FIRST_CLEAR = false;    
-        }
         //  Create brush
         let brush = self.solid_brush(color);
-        //  Create rectangle to fill the screen
-        let shape = Rect::new(0., 0., 
-            DISPLAY_WIDTH  as f64 - 1., 
-            DISPLAY_HEIGHT as f64 - 1.);
-        //  Fill the screen
-        self.fill(shape, &brush);
+        let fill = self.convert_brush(&brush);
+        //  Fill the clip region, ignoring the transform. Stream the rows in blocks to keep each draw request small.
+        let clip = self.state.clip;
+        if clip.is_empty() { return; }
+        let mut y = clip.y0;
+        while y < clip.y1 {
+            let y_end = (y + CLEAR_BLOCK_ROWS).min(clip.y1);
+            let spans = (y..y_end).map(|y| raster::Span { y, x0: clip.x0, x1: clip.x1, alpha: 0xff });
+            self.draw_spans(spans, &fill);
+            y = y_end;
+        }
     }
 
     fn solid_brush(&mut self, color: Color) -> brush::Brush {
         brush::Brush::Solid(color.as_rgba_u32())
     }
 
-    /* ////  TODO
-        fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Brush, Error> {
-            match gradient.into() {
-                FixedGradient::Linear(linear) => {
-                    let (x0, y0) = (linear.start.x, linear.start.y);
-                    let (x1, y1) = (linear.end.x, linear.end.y);
-                    let lg = embedded_graphics::LinearGradient::new(x0, y0, x1, y1);
-                    set_gradient_stops!(&lg, &linear.stops);
-                    Ok(Brush::Linear(lg))
-                }
-                FixedGradient::Radial(radial) => {
-                    let (xc, yc) = (radial.center.x, radial.center.y);
-                    let (xo, yo) = (radial.origin_offset.x, radial.origin_offset.y);
-                    let r = radial.radius;
-                    let rg = embedded_graphics::RadialGradient::new(xc + xo, yc + yo, 0.0, xc, yc, r);
-                    set_gradient_stops!(&rg, &radial.stops);
-                    Ok(Brush::Radial(rg))
-                }
-            }
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<brush::Brush, Error> {
+        match gradient.into() {
+            FixedGradient::Linear(linear) => Ok(brush::Brush::Linear(linear)),
+            FixedGradient::Radial(radial) => Ok(brush::Brush::Radial(radial)),
         }
-    */ ////
+    }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
-        let brush = brush.make_brush(self, || shape.bounding_box());
-        //  TODO: Handle Bezier path
-        //  self.set_path(shape);
-
-        //  TODO: For now we fill the bounding box
-        let bounding_box = shape.bounding_box();
-        let left_top = Coord::new(bounding_box.x0 as i32, bounding_box.y0 as i32);
-        let right_btm = Coord::new(bounding_box.x1 as i32, bounding_box.y1 as i32);
-
-        //  Get fill color
-        let fill = self.convert_brush(&brush);
-
-        //  Create rectangle with fill
-        let rect = Rectangle::<Rgb565>
-            ::new(left_top, right_btm)
-            .fill(Some(fill))
-            .translate(get_transform_stack())
-            ;
-        display::draw_to_display(rect);
-
-        ////self.ctx.set_fill_rule(embedded_graphics::FillRule::Winding);
-        ////self.ctx.fill();
+        self.fill_with_rule(shape, brush, raster::FillRule::NonZero);
     }
 
     fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
-        self.fill(shape, brush);
-        /* TODO
-        let brush = brush.make_brush(self, || shape.bounding_box());
-        self.set_path(shape);
-        self.set_brush(&brush);
-        self.ctx.set_fill_rule(embedded_graphics::FillRule::EvenOdd);
-        self.ctx.fill();
-        */
+        self.fill_with_rule(shape, brush, raster::FillRule::EvenOdd);
     }
 
-    fn clip(&mut self, _shape: impl Shape) {
-        console::print("no clip\n");  ////  TODO
-        /*
-        self.set_path(shape);
-        self.ctx.set_fill_rule(embedded_graphics::FillRule::Winding);
-        self.ctx.clip();
-        */
+    fn clip(&mut self, shape: impl Shape) {
+        //  Find the pixels of the shape inside the current clip, and check whether they form a rectangle
+        let transform = self.state.transform;
+        let outline = raster::ShapeOutline::new(&shape, transform, flatten_tolerance(transform));
+        let mut bounds = ClipRect { x0: 0, y0: 0, x1: 0, y1: 0 };
+        let mut area = 0;
+        for span in raster::Rasterizer::new(&outline, raster::FillRule::NonZero, self.state.clip.to_rect()) {
+            bounds = bounds.union_span(&span);
+            area += span.x1 - span.x0;
+        }
+        let clip = self.state.clip;
+        self.state.clip = bounds;
+
+        //  Clip to the bounds if the shape is a rectangle, or if there is no clip mask
+        if area == bounds.area() { return; }
+        let depth = self.state.clip_depth;
+        if let Some(mask) = &mut self.mask {
+            if depth == u8::max_value() { return; }  //  Too many clip shapes
+            mask.clip(raster::Rasterizer::new(&outline, raster::FillRule::NonZero, clip.to_rect()), depth);
+            self.state.clip_depth = depth + 1;
+        }
     }
 
     fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
-        let brush = brush.make_brush(self, || shape.bounding_box());
-
-        //  Get stroke color
-        let stroke = self.convert_brush(&brush);
-
-        //  Draw a line for each segment of the Bezier path
-        let mut first: Option<Point> = None;
-        let mut last = Point::ZERO;
-        for el in shape.to_bez_path(0.1) {  //  Previously 1e-3
-            match el {
-                PathEl::MoveTo(p) => {
-                    ////self.ctx.move_to(p.x, p.y);
-                    if first.is_none() { first = Some(p); }
-                    last = p;
-                }
-                PathEl::LineTo(p) => {
-                    //  Draw line from last to p with styled stroke
-                    let last_coord = Coord::new(last.x as i32, last.y as i32);
-                    let p_coord = Coord::new(p.x as i32, p.y as i32);
-                    let line = Line::<Rgb565>
-                        ::new(last_coord, p_coord)
-                        .stroke(Some(stroke))
-                        .stroke_width(width as u8)
-                        .translate(get_transform_stack())
-                        ;
-                    display::draw_to_display(line);
-                    ////self.ctx.line_to(p.x, p.y);
-                    if first.is_none() { first = Some(p); }
-                    last = p;
-                }
-                PathEl::QuadTo(p1, p2) => {
-                    //  TODO: Handle quad
-                    //  Draw line from last to p2 with styled stroke
-                    let last_coord = Coord::new(last.x as i32, last.y as i32);
-                    let p2_coord = Coord::new(p2.x as i32, p2.y as i32);
-                    let line = Line::<Rgb565>
-                        ::new(last_coord, p2_coord)
-                        .stroke(Some(stroke))
-                        .stroke_width(width as u8)
-                        .translate(get_transform_stack())
-                        ;
-                    display::draw_to_display(line);
-                    ////let q = QuadBez::new(last, p1, p2);
-                    ////let c = q.raise();
-                    ////self.ctx
-                        ////.curve_to(c.p1.x, c.p1.y, c.p2.x, c.p2.y, p2.x, p2.y);
-                    if first.is_none() { first = Some(p1); }
-                    last = p2;
-                }
-                PathEl::CurveTo(p1, _p2, p3) => {
-                    //  TODO: Handle curve
-                    //  Draw line from last to p3 with styled stroke
-                    let last_coord = Coord::new(last.x as i32, last.y as i32);
-                    let p3_coord = Coord::new(p3.x as i32, p3.y as i32);
-                    let line = Line::<Rgb565>
-                        ::new(last_coord, p3_coord)
-                        .stroke(Some(stroke))
-                        .stroke_width(width as u8)
-                        .translate(get_transform_stack())
-                        ;
-                    display::draw_to_display(line);
-                    ////self.ctx.curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y);
-                    if first.is_none() { first = Some(p1); }
-                    last = p3;
-                }
-                PathEl::ClosePath => {
-                    let first = first.expect("close path fail");
-                    let last_coord = Coord::new(last.x as i32, last.y as i32);
-                    let first_coord = Coord::new(first.x as i32, first.y as i32);
-                    let line = Line::<Rgb565>
-                        ::new(last_coord, first_coord)
-                        .stroke(Some(stroke))
-                        .stroke_width(width as u8)
-                        .translate(get_transform_stack())
-                        ;
-                    display::draw_to_display(line);
-                    ////self.ctx.close_path()
-                }
-            }
-        }
-
-        ////self.ctx.stroke();
+        self.stroke_outline(shape, brush, width, None);
     }
 
     fn stroke_styled(
@@ -261,20 +271,13 @@ impl RenderContext for EmbedRenderContext {
         shape: impl Shape,
         brush: &impl IntoBrush<Self>,
         width: f64,
-        _style: &StrokeStyle,
+        style: &StrokeStyle,
     ) {
-        self.stroke(shape, brush, width);
-        /* TODO
-            let brush = brush.make_brush(self, || shape.bounding_box());
-            self.set_path(shape);
-            self.set_stroke(width, Some(style));
-            self.set_brush(&brush);
-            self.ctx.stroke();
-        */
+        self.stroke_outline(shape, brush, width, Some(style));
     }
 
     fn text(&mut self) -> &mut Self::Text {
-        unsafe { &mut EMBED_TEXT }
+        &mut self.text
     }
 
     fn draw_text(
@@ -283,25 +286,35 @@ impl RenderContext for EmbedRenderContext {
         pos: impl Into<Point>,
         brush: &impl IntoBrush<Self>,
     ) {
-        let brush = brush.make_brush(self, || Rect::ZERO);
         let pos = pos.into();
+        let brush = brush.make_brush(self, || layout.image_bounds() + pos.to_vec2());
 
-        //  Get stroke color
+        //  Get stroke pattern
         let stroke = self.convert_brush(&brush);
 
-        //  Create text
-        let text = embedded_graphics::fonts::Font12x16::<Rgb565>
-            ::render_str(&layout.text)
-            .stroke(Some(stroke))
-            .fill(Some(Rgb565::from((   0x00, 0x00, 0x00 ))))  //  TODO: Remove black background fill
-            .translate(Coord::new(pos.x as i32, pos.y as i32))
-            .translate(get_transform_stack())
-            ;
-        
-        //  Render text to display
-        display::draw_to_display(text);
-
-        // TODO: bounding box for text
+        //  Render each line of text to display with the layout's font, without the trailing whitespace.
+        //  `pos` is the baseline of the first line, and each line is aligned horizontally.
+        for line in layout.lines() {
+            let text = layout.visible_text(&line);
+            let baseline = line.y_offset + line.baseline - layout.ascent();
+            let transform = self.state.transform * Affine::translate(pos.to_vec2() + Vec2::new(layout.line_x(&line), baseline));
+            match layout.font() {
+                text::EmbedFont::Bitmap(font) => {
+                    //  Bitmap glyphs are rendered from the top of the character cells
+                    let transform = transform * Affine::translate((0., -(font.baseline() as f64)));
+                    self.draw_bitmap_text(font, text, transform, &stroke)
+                }
+                #[cfg(feature = "truetype")]
+                text::EmbedFont::Outline(font) => {
+                    //  Fill the outline of each glyph on the baseline
+                    for (glyph, x) in font.glyphs(text) {
+                        let outline = font.outline(glyph, transform * Affine::translate((x, 0.)), FLATTEN_TOLERANCE);
+                        self.fill_outline(&outline, raster::FillRule::NonZero, &stroke);
+                    }
+                }
+            }
+        }
+
         /*
         self.ctx.set_scaled_font(&layout.font);
         self.set_brush(&brush);
@@ -311,18 +324,18 @@ impl RenderContext for EmbedRenderContext {
     }
 
     fn save(&mut self) -> Result<(), Error> {
-        unsafe {
-            TRANSFORM_STACK.push(Point::ZERO)
-                .expect("transform stack overflow");
-        }
-        Ok(())
+        self.saved.push(self.state)
+            .map_err(|_| Error {})  //  Too many saved states
     }
 
     fn restore(&mut self) -> Result<(), Error> {
-        unsafe { 
-            TRANSFORM_STACK.pop()
-                .expect("transform stack empty"); 
+        let state = self.saved.pop()
+            .ok_or(Error {})?;  //  No saved state
+        //  Undo the clip shapes in the clip mask. They were applied inside the saved clip rectangle.
+        if state.clip_depth < self.state.clip_depth {
+            if let Some(mask) = &mut self.mask { mask.restore(state.clip_depth, state.clip); }
         }
+        self.state = state;
         Ok(())
     }
 
@@ -330,118 +343,167 @@ impl RenderContext for EmbedRenderContext {
         self.status()
     }
 
-    fn transform(&mut self, _transform: Affine) {
-        /* console::print("transform ");
-        for f in &_transform.0 {
-            console::printint(*f as i32);            
-            console::print(", ");            
-        }
-        console::print("\n"); */
-        unsafe {
-            let mut point = TRANSFORM_STACK.pop()
-                .expect("transform stack empty");
-            point.x += &_transform.0[4];
-            point.y += &_transform.0[5];
-            TRANSFORM_STACK.push(point)
-                .expect("never");
-        }
+    fn transform(&mut self, transform: Affine) {
+        //  Apply the new transform to user coordinates before the current transform
+        self.state.transform = self.state.transform * transform;
+    }
+
+    fn make_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        buf: &'static [u8],
+        format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        //  The pixels are converted when the image is drawn
+        ImageBuf::new(width, height, buf, format)
+    }
 
-        //cortex_m::asm::bkpt(); ////
-        ////self.ctx.transform(affine_to_matrix(transform));        
+    fn draw_image(&mut self, image: &Self::Image, rect: impl Into<Rect>, interp: InterpolationMode) {
+        //  Fill the rectangle with the image, scaled with the interpolation mode
+        let rect = rect.into();
+        if rect.width() == 0. || rect.height() == 0. { return; }
+        let transform = self.state.transform;
+        let pattern = Pattern::Image(image::ImageShader::new(*image, rect, transform, interp));
+        let outline = raster::ShapeOutline::new(&rect, transform, flatten_tolerance(transform));
+        self.fill_outline(&outline, raster::FillRule::NonZero, &pattern);
     }
 }
 
-/*
-    fn convert_line_cap(line_cap: LineCap) -> embedded_graphics::LineCap {
-        match line_cap {
-            LineCap::Butt => embedded_graphics::LineCap::Butt,
-            LineCap::Round => embedded_graphics::LineCap::Round,
-            LineCap::Square => embedded_graphics::LineCap::Square,
-        }
+impl<'a, D: Drawing<Rgb565>, N: ArrayLength<u8>> EmbedRenderContext<'a, D, N> {
+    /// Fill the shape with the fill rule
+    fn fill_with_rule(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, rule: raster::FillRule) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+
+        //  Get fill pattern
+        let fill = self.convert_brush(&brush);
+
+        //  Rasterize the flattened shape and render the spans of pixels
+        let transform = self.state.transform;
+        let outline = raster::ShapeOutline::new(&shape, transform, flatten_tolerance(transform));
+        self.fill_outline(&outline, rule, &fill);
     }
 
-    fn convert_line_join(line_join: LineJoin) -> embedded_graphics::LineJoin {
-        match line_join {
-            LineJoin::Miter => embedded_graphics::LineJoin::Miter,
-            LineJoin::Round => embedded_graphics::LineJoin::Round,
-            LineJoin::Bevel => embedded_graphics::LineJoin::Bevel,
+    /// Stroke the shape with the width and optional style, by filling the outline of the stroke
+    fn stroke_outline(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64, style: Option<&StrokeStyle>) {
+        let brush = brush.make_brush(self, || shape.bounding_box());
+
+        //  Get stroke pattern
+        let stroke = self.convert_brush(&brush);
+
+        //  Rasterize the outline of the stroke, in pixel coordinates
+        let transform = self.state.transform;
+        let mut style = stroke::Style::new(width, style, transform_scale(transform), FLATTEN_TOLERANCE);
+        if !self.antialias {
+            //  Aliased strokes are at least 1 pixel wide, so that thin strokes have no gaps
+            style = style.with_min_width(1.);
         }
+        let outline = stroke::StrokeOutline::new(&shape, transform, flatten_tolerance(transform), style);
+        self.fill_outline(&outline, raster::FillRule::NonZero, &stroke);
     }
-*/
 
-impl EmbedRenderContext {
-    /// Get the source pattern for the brush
-    fn convert_brush(&mut self, brush: &brush::Brush) -> Rgb565 {
-        match *brush {
-            brush::Brush::Solid(rgba) => {
-                Rgb565::from((
-                    (rgba >> 24) as u8,  //  Red
-                    (rgba >> 16) as u8,  //  Green
-                    (rgba >>  8) as u8   //  Blue
-                ))  //  Alpha transparency not used: rgba as u8
+    /// Render the text with the bitmap font, stroking the glyphs with the pattern.
+    /// Only the pixels of the glyphs are drawn, so the pixels between the glyphs are unchanged.
+    fn draw_bitmap_text(&mut self, font: &text::BitmapFont, text: &str, transform: Affine, stroke: &Pattern) {
+        font.render(text, FOREGROUND, None, &mut |pixels| {
+            if is_translation(transform) {
+                //  Bitmap fonts are rendered faster without rotation and scaling. The sparse pixels
+                //  of the glyphs are passed together, so that the display driver may batch them into blocks.
+                //  Round the origin like the rasterizer, which draws the pixels whose centres are inside
+                let origin = transform * Point::ZERO;
+                let (dx, dy) = (libm::ceil(origin.x - 0.5) as i32, libm::ceil(origin.y - 0.5) as i32);
+                self.draw_pixels(pixels.filter_map(|Pixel(coord, _)| {
+                    let (x, y) = (coord.0 as i32 + dx, coord.1 as i32 + dy);
+                    if x < 0 || y < 0 { return None; }
+                    let (color, alpha) = stroke.color_at(x, y);
+                    Some((Pixel(UnsignedCoord::new(x as u32, y as u32), color), alpha))
+                }));
+            } else {
+                //  Render each run of adjacent pixels in a row of the glyphs as a transformed rectangle
+                let mut run: Option<(u32, u32, u32)> = None;  //  Row, first column and last column (exclusive)
+                for Pixel(coord, _) in pixels {
+                    match run {
+                        Some((y, x0, x1)) if y == coord.1 && x1 == coord.0 => run = Some((y, x0, x1 + 1)),
+                        _ => {
+                            if let Some(run) = run { self.draw_glyph_run(run, transform, stroke); }
+                            run = Some((coord.1, coord.0, coord.0 + 1));
+                        }
+                    }
+                }
+                if let Some(run) = run { self.draw_glyph_run(run, transform, stroke); }
             }
-            ////Brush::Linear(ref linear) => self.ctx.set_source(linear),
-            ////Brush::Radial(ref radial) => self.ctx.set_source(radial),
-        }
+        });
     }
 
-    /* TODO
-        /// Set the stroke parameters.
-        fn set_stroke(&mut self, width: f64, style: Option<&StrokeStyle>) {
-            self.ctx.set_line_width(width);
-
-            let line_join = style
-                .and_then(|style| style.line_join)
-                .unwrap_or(LineJoin::Miter);
-            self.ctx.set_line_join(convert_line_join(line_join));
-
-            let line_cap = style
-                .and_then(|style| style.line_cap)
-                .unwrap_or(LineCap::Butt);
-            self.ctx.set_line_cap(convert_line_cap(line_cap));
-
-            let miter_limit = style.and_then(|style| style.miter_limit).unwrap_or(10.0);
-            self.ctx.set_miter_limit(miter_limit);
+    /// Render a run of glyph pixels from the row, first column and last column (exclusive), transformed to pixel coordinates
+    fn draw_glyph_run(&mut self, (y, x0, x1): (u32, u32, u32), transform: Affine, stroke: &Pattern) {
+        let rect = Rect::new(x0 as f64, y as f64, x1 as f64, y as f64 + 1.);
+        let outline = raster::ShapeOutline::new(&rect, transform, FLATTEN_TOLERANCE);
+        let spans = raster::Rasterizer::new(&outline, raster::FillRule::NonZero, self.state.clip.to_rect());
+        self.draw_spans(spans, stroke);
+    }
 
-            match style.and_then(|style| style.dash.as_ref()) {
-                None => self.ctx.set_dash(&[], 0.0),
-                Some((dashes, offset)) => self.ctx.set_dash(dashes, *offset),
-            }
+    /// Fill the outline (in pixel coordinates) with the fill rule and pattern, anti-aliased if enabled
+    fn fill_outline(&mut self, outline: &impl raster::Outline, rule: raster::FillRule, pattern: &Pattern) {
+        let bounds = self.state.clip.to_rect();
+        if self.antialias {
+            self.draw_spans(raster::AntialiasRasterizer::new(outline, rule, bounds), pattern);
+        } else {
+            self.draw_spans(raster::Rasterizer::new(outline, rule, bounds), pattern);
         }
+    }
 
-        fn set_path(&mut self, shape: impl Shape) {
-            // This shouldn't be necessary, we always leave the context in no-path
-            // state. But just in case, and it should be harmless.
-            ////self.ctx.new_path();
-            let mut last = Point::ZERO;
-            for el in shape.to_bez_path(0.1) {  //  Previously 1e-3
-                match el {
-                    PathEl::MoveTo(p) => {
-                        ////self.ctx.move_to(p.x, p.y);
-                        last = p;
-                    }
-                    PathEl::LineTo(p) => {
-                        ////self.ctx.line_to(p.x, p.y);
-                        last = p;
-                    }
-                    PathEl::QuadTo(p1, p2) => {
-                        let q = QuadBez::new(last, p1, p2);
-                        let c = q.raise();
-                        ////self.ctx
-                            ////.curve_to(c.p1.x, c.p1.y, c.p2.x, c.p2.y, p2.x, p2.y);
-                        last = p2;
-                    }
-                    PathEl::CurveTo(p1, p2, p3) => {
-                        ////self.ctx.curve_to(p1.x, p1.y, p2.x, p2.y, p3.x, p3.y);
-                        last = p3;
-                    }
-                    PathEl::ClosePath => {
-                        ////self.ctx.close_path()
-                    }
+    /// Render the spans of pixels with the pattern. The spans are rendered row by row, so that
+    /// the display driver may batch the pixels into rows and blocks.
+    fn draw_spans(&mut self, spans: impl Iterator<Item = raster::Span>, pattern: &Pattern) {
+        let pixels = spans.flat_map(move |span| {
+            (span.x0..span.x1).map(move |x| {
+                let (color, alpha) = pattern.color_at(x, span.y);
+                let alpha = if span.alpha == 0xff { alpha } else { (alpha as u32 * span.alpha as u32 / 0xff) as u8 };
+                (Pixel(UnsignedCoord::new(x as u32, span.y as u32), color), alpha)
+            })
+        });
+        self.draw_pixels(pixels);
+    }
+
+    /// Render the pixels (with their alpha) that are inside the clip region. Translucent pixels are
+    /// blended with the shadow buffer, or rendered with the alpha fallback if there is no shadow buffer.
+    fn draw_pixels(&mut self, pixels: impl IntoIterator<Item = (Pixel<Rgb565>, u8)>) {
+        let clip = self.state.clip;
+        let depth = self.state.clip_depth;
+        let mask = self.mask.as_ref();
+        let fallback = self.fallback;
+        let mut shadow = self.shadow.as_mut();
+        let pixels = pixels.into_iter()
+            .filter(move |(Pixel(coord, _), alpha)| {
+                *alpha > 0
+                && clip.contains(coord.0 as i32, coord.1 as i32)
+                && mask.map_or(true, |mask| mask.is_visible(coord.0, coord.1, depth))
+            })
+            .filter_map(move |(Pixel(coord, color), alpha)| {
+                match shadow {
+                    Some(ref mut shadow) => Some(Pixel(coord, shadow.blend(coord.0, coord.1, color, alpha))),
+                    None if fallback.is_visible(coord.0, coord.1, alpha) => Some(Pixel(coord, color)),
+                    None => None,
                 }
+            });
+        self.display.draw(pixels);
+    }
+
+    /// Get the source pattern for the brush, in pixel coordinates for the current transform
+    fn convert_brush(&mut self, brush: &brush::Brush) -> Pattern {
+        match *brush {
+            brush::Brush::Solid(rgba) => {
+                Pattern::Solid(Rgb565::from((
+                    (rgba >> 24) as u8,  //  Red
+                    (rgba >> 16) as u8,  //  Green
+                    (rgba >>  8) as u8   //  Blue
+                )), rgba as u8)          //  Alpha
             }
+            brush::Brush::Linear(ref linear) => Pattern::Linear(gradient::LinearShader::new(linear, self.state.transform)),
+            brush::Brush::Radial(ref radial) => Pattern::Radial(gradient::RadialShader::new(radial, self.state.transform)),
         }
-    */
+    }
 }
 
 /* ////
@@ -0,0 +1,220 @@
+//! Linear and radial gradients, evaluated at the centre of each pixel in 16.16 fixed-point.
+//! The gradients are specified in user coordinates, so the current transform is inverted when
+//! the gradient is prepared for rendering.
+use piet::kurbo::{Affine, Vec2};
+use piet::{FixedLinearGradient, FixedRadialGradient, GradientStop};
+use embedded_graphics::pixelcolor::Rgb565;
+
+/// Max number of gradient stops, same as piet
+type MaxStops = heapless::consts::U3;
+
+/// Number of fractional bits for fixed-point values
+pub const FRAC_BITS: i32 = 16;
+/// 1.0 in fixed-point
+const ONE: i64 = 1 << FRAC_BITS;
+/// Max magnitude of fixed-point values
+const MAX_FIXED: i64 = 1 << 40;
+/// Max magnitude of the radial gradient coordinates, in radii. Keeps the fixed-point arithmetic within range.
+const MAX_RADII: i64 = 64;
+
+/// Gradient stop with the position in fixed-point
+#[derive(Clone, Copy)]
+struct Stop {
+    /// Position of the stop, from 0.0 to 1.0 in fixed-point
+    pos: i64,
+    /// Red, green, blue and alpha components of the colour
+    rgba: [i64; 4],
+}
+
+/// Gradient stops, sorted by position
+#[derive(Clone)]
+struct Stops(heapless::Vec<Stop, MaxStops>);
+
+impl Stops {
+    /// Convert the piet gradient stops to fixed-point
+    fn new(stops: &[GradientStop]) -> Self {
+        let mut result = heapless::Vec::new();
+        for stop in stops {
+            let c = stop.color.as_rgba_u32();
+            let rgba = [(c >> 24) as i64, (c >> 16 & 0xff) as i64, (c >> 8 & 0xff) as i64, (c & 0xff) as i64];
+            result.push(Stop { pos: to_fixed(stop.pos as f64), rgba }).ok();  //  Never fails because piet has the same limit
+        }
+        result.sort_unstable_by_key(|stop: &Stop| stop.pos);
+        Stops(result)
+    }
+
+    /// Return the colour and alpha at position `t` (in fixed-point). Positions beyond the first and last stops
+    /// are padded with the colours of the first and last stops.
+    fn color_at(&self, t: i64) -> (Rgb565, u8) {
+        let stops = &self.0;
+        if stops.is_empty() { return (Rgb565::from((0, 0, 0)), 0); }
+        let mut rgba = stops[0].rgba;
+        for pair in stops.windows(2) {
+            let (s0, s1) = (pair[0], pair[1]);
+            if t >= s1.pos {
+                //  Beyond this pair of stops
+                rgba = s1.rgba;
+            } else if t > s0.pos {
+                //  Interpolate between the stops, with 8 bits of precision
+                let frac = ((t - s0.pos) << 8) / (s1.pos - s0.pos);
+                for (c, (c0, c1)) in rgba.iter_mut().zip(s0.rgba.iter().zip(s1.rgba.iter())) {
+                    *c = c0 + (((c1 - c0) * frac) >> 8);
+                }
+                break;
+            }
+        }
+        (Rgb565::from((rgba[0] as u8, rgba[1] as u8, rgba[2] as u8)), rgba[3] as u8)
+    }
+}
+
+/// Value that varies linearly with the pixel coordinates, in fixed-point
+#[derive(Clone, Copy)]
+pub struct Linear {
+    /// Value at the centre of pixel (0,0)
+    v0:   i64,
+    /// Change in value per column
+    dvdx: i64,
+    /// Change in value per row
+    dvdy: i64,
+}
+
+impl Linear {
+    /// Convert the coefficients of the function `v0 + dvdx * x + dvdy * y` of pixel coordinates to fixed-point,
+    /// and sample at the pixel centres
+    pub fn new(v0: f64, dvdx: f64, dvdy: f64) -> Self {
+        Linear {
+            v0:   to_fixed(v0 + (dvdx + dvdy) / 2.),
+            dvdx: to_fixed(dvdx),
+            dvdy: to_fixed(dvdy),
+        }
+    }
+
+    /// Return the value at pixel (x,y)
+    pub fn at(&self, x: i32, y: i32) -> i64 {
+        self.v0 + self.dvdx * x as i64 + self.dvdy * y as i64
+    }
+}
+
+/// Linear gradient prepared for rendering in pixel coordinates
+#[derive(Clone)]
+pub struct LinearShader {
+    /// Position along the gradient for each pixel
+    t:     Linear,
+    /// Gradient stops
+    stops: Stops,
+}
+
+impl LinearShader {
+    /// Prepare the linear gradient (in user coordinates) for rendering with the transform
+    pub fn new(gradient: &FixedLinearGradient, transform: Affine) -> Self {
+        //  Project the user coordinates of the pixel onto the gradient vector
+        let [a, b, c, d, e, f] = inverse(transform).as_coeffs();
+        let dir = gradient.end - gradient.start;
+        let len2 = dir.hypot2();
+        let g = if len2 > 0. { dir / len2 } else { Vec2::ZERO };
+        let (sx, sy) = (gradient.start.x, gradient.start.y);
+        LinearShader {
+            t: Linear::new(
+                g.x * (e - sx) + g.y * (f - sy),
+                g.x * a + g.y * b,
+                g.x * c + g.y * d,
+            ),
+            stops: Stops::new(&gradient.stops),
+        }
+    }
+
+    /// Return the colour and alpha of pixel (x,y)
+    pub fn color_at(&self, x: i32, y: i32) -> (Rgb565, u8) {
+        self.stops.color_at(self.t.at(x, y))
+    }
+}
+
+/// Radial gradient prepared for rendering in pixel coordinates. The gradient position is 0.0 at the focal
+/// point (the origin) and 1.0 on the circle.
+#[derive(Clone)]
+pub struct RadialShader {
+    /// Horizontal distance from the focal point for each pixel, in radii
+    qx:    Linear,
+    /// Vertical distance from the focal point for each pixel, in radii
+    qy:    Linear,
+    /// Horizontal distance from the focal point to the centre, in radii
+    ex:    i64,
+    /// Vertical distance from the focal point to the centre, in radii
+    ey:    i64,
+    /// Squared distance from the focal point to the centre minus 1, in radii
+    a:     i64,
+    /// Gradient stops
+    stops: Stops,
+}
+
+impl RadialShader {
+    /// Prepare the radial gradient (in user coordinates) for rendering with the transform
+    pub fn new(gradient: &FixedRadialGradient, transform: Affine) -> Self {
+        let [a, b, c, d, e, f] = inverse(transform).as_coeffs();
+        let r = if gradient.radius > 0. { gradient.radius } else { 1. };
+        let focal = gradient.center + gradient.origin_offset;
+        let max = (MAX_RADII / 2) as f64;
+        let to_centre = (gradient.center - focal) / r;
+        let to_centre = Vec2::new(to_centre.x.max(-max).min(max), to_centre.y.max(-max).min(max));
+        RadialShader {
+            qx:    Linear::new((e - focal.x) / r, a / r, c / r),
+            qy:    Linear::new((f - focal.y) / r, b / r, d / r),
+            ex:    to_fixed(to_centre.x),
+            ey:    to_fixed(to_centre.y),
+            a:     to_fixed(to_centre.hypot2() - 1.),
+            stops: Stops::new(&gradient.stops),
+        }
+    }
+
+    /// Return the colour and alpha of pixel (x,y). Pixels outside the cone of circles, when the focal point
+    /// is on or outside the circle, are transparent.
+    pub fn color_at(&self, x: i32, y: i32) -> (Rgb565, u8) {
+        //  Find the largest t such that the pixel lies on the circle of radius t, centred at
+        //  the focal point plus t times the distance to the centre. Solve a t^2 - 2 qe t + qq = 0.
+        let max = MAX_RADII * ONE;
+        let qx = self.qx.at(x, y).max(-max).min(max);
+        let qy = self.qy.at(x, y).max(-max).min(max);
+        let qe = (qx * self.ex + qy * self.ey) >> FRAC_BITS;
+        let qq = (qx * qx + qy * qy) >> FRAC_BITS;
+        let t =
+            if self.a == 0 {
+                //  Focal point is on the circle
+                if qe > 0 { (qq << FRAC_BITS) / (2 * qe) } else { -1 }
+            } else {
+                let discriminant = qe * qe - self.a * qq;
+                if discriminant < 0 { -1 }  //  Outside the cone when the focal point is outside the circle
+                else {
+                    let s = isqrt(discriminant as u64) as i64;
+                    (((qe + s) << FRAC_BITS) / self.a).max(((qe - s) << FRAC_BITS) / self.a)
+                }
+            };
+        //  Circles with negative radius aren't drawn
+        if t < 0 { return (Rgb565::from((0, 0, 0)), 0); }
+        self.stops.color_at(t)
+    }
+}
+
+/// Return the inverse of the transform, or the identity if the transform can't be inverted
+pub fn inverse(transform: Affine) -> Affine {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    if a * d - b * c == 0. { return Affine::default(); }
+    transform.inverse()
+}
+
+/// Convert the value to fixed-point
+fn to_fixed(x: f64) -> i64 {
+    //  Clamp to keep the fixed-point arithmetic within range
+    libm::round(x * ONE as f64).max(-MAX_FIXED as f64).min(MAX_FIXED as f64) as i64
+}
+
+/// Return the integer square root of `n`, rounded down
+fn isqrt(n: u64) -> u64 {
+    if n < 2 { return n; }
+    //  Newton's method, starting from a power of 2 that is at least the square root
+    let mut x = 1u64 << ((64 - n.leading_zeros() + 1) / 2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x { return x; }
+        x = y;
+    }
+}
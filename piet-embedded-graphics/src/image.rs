@@ -1,87 +1,137 @@
-/* ////   
-    fn make_image(
-        &mut self,
-        width: usize,
-        height: usize,
-        buf: &[u8],
-        format: ImageFormat,
-    ) -> Result<Self::Image, Error> {
-        let embedded_graphics_fmt = match format {
-            ImageFormat::Rgb => Format::Rgb24,
-            ImageFormat::RgbaSeparate | ImageFormat::RgbaPremul => Format::ARgb32,
-            _ => return Err(new_error(ErrorKind::NotSupported)),
-        };
-        let mut image = ImageSurface::create(embedded_graphics_fmt, width as i32, height as i32).wrap()?;
-        // Confident no borrow errors because we just created it.
-        let bytes_per_pixel = format.bytes_per_pixel();
-        let bytes_per_row = width * bytes_per_pixel;
-        let stride = image.get_stride() as usize;
-        {
-            let mut data = image.get_data().wrap()?;
-            for y in 0..height {
-                let src_off = y * bytes_per_row;
-                let dst_off = y * stride;
-                match format {
-                    ImageFormat::Rgb => {
-                        for x in 0..width {
-                            data[dst_off + x * 4 + 0] = buf[src_off + x * 3 + 2];
-                            data[dst_off + x * 4 + 1] = buf[src_off + x * 3 + 1];
-                            data[dst_off + x * 4 + 2] = buf[src_off + x * 3 + 0];
-                        }
-                    }
-                    ImageFormat::RgbaPremul => {
-                        // It's annoying that Embed exposes only ARGB. Ah well. Let's
-                        // hope that LLVM generates pretty good code for this.
-                        // TODO: consider adding BgraPremul format.
-                        for x in 0..width {
-                            data[dst_off + x * 4 + 0] = buf[src_off + x * 4 + 2];
-                            data[dst_off + x * 4 + 1] = buf[src_off + x * 4 + 1];
-                            data[dst_off + x * 4 + 2] = buf[src_off + x * 4 + 0];
-                            data[dst_off + x * 4 + 3] = buf[src_off + x * 4 + 3];
-                        }
-                    }
-                    ImageFormat::RgbaSeparate => {
-                        fn premul(x: u8, a: u8) -> u8 {
-                            let y = (x as u16) * (a as u16);
-                            ((y + (y >> 8) + 0x80) >> 8) as u8
-                        }
-                        for x in 0..width {
-                            let a = buf[src_off + x * 4 + 3];
-                            data[dst_off + x * 4 + 0] = premul(buf[src_off + x * 4 + 2], a);
-                            data[dst_off + x * 4 + 1] = premul(buf[src_off + x * 4 + 1], a);
-                            data[dst_off + x * 4 + 2] = premul(buf[src_off + x * 4 + 0], a);
-                            data[dst_off + x * 4 + 3] = a;
-                        }
-                    }
-                    _ => return Err(new_error(ErrorKind::NotSupported)),
+//! Bitmap images drawn as a pattern. Each pixel of the destination rectangle is mapped back to a point in the
+//! image in 16.16 fixed-point, and the nearest image pixel (or the 4 image pixels around the point, blended
+//! bilinearly) is converted to Rgb565 with alpha. The image is never copied or scaled in memory, so the pixels
+//! are converted one at a time as the rows of the rectangle are rendered.
+use piet::kurbo::{Affine, Rect};
+use piet::{ImageBuf, ImageFormat, InterpolationMode};
+use embedded_graphics::pixelcolor::Rgb565;
+use crate::gradient::{self, Linear, FRAC_BITS};
+
+/// Colour and alpha of transparent pixels
+const TRANSPARENT: (Rgb565, u8) = (Rgb565(0), 0);
+/// 0.5 in fixed-point, for the centres of the image pixels
+const HALF: i64 = 1 << (FRAC_BITS - 1);
+/// Number of fractional bits for the bilinear weights
+const WEIGHT_BITS: i32 = 8;
+
+/// Image prepared for rendering into a rectangle, in pixel coordinates
+#[derive(Clone)]
+pub struct ImageShader {
+    /// Image to be rendered
+    image:  ImageBuf<'static>,
+    /// Image column for each pixel
+    u:      Linear,
+    /// Image row for each pixel
+    v:      Linear,
+    /// How the image pixels are sampled
+    interp: InterpolationMode,
+}
+
+impl ImageShader {
+    /// Prepare the image for rendering into the rectangle (in user coordinates) with the transform and interpolation mode
+    pub fn new(image: ImageBuf<'static>, rect: Rect, transform: Affine, interp: InterpolationMode) -> Self {
+        //  Map the user coordinates of the pixel to the image, scaled from the rectangle
+        let [a, b, c, d, e, f] = gradient::inverse(transform).as_coeffs();
+        let sx = image.width() as f64 / rect.width();
+        let sy = image.height() as f64 / rect.height();
+        ImageShader {
+            image,
+            u: Linear::new(sx * (e - rect.x0), sx * a, sx * c),
+            v: Linear::new(sy * (f - rect.y0), sy * b, sy * d),
+            interp,
+        }
+    }
+
+    /// Return the colour and alpha of pixel (x,y), sampled from the image with the interpolation mode
+    pub fn color_at(&self, x: i32, y: i32) -> (Rgb565, u8) {
+        let (u, v) = (self.u.at(x, y), self.v.at(x, y));
+        match self.interp {
+            InterpolationMode::NearestNeighbor => self.pixel(u >> FRAC_BITS, v >> FRAC_BITS),
+            InterpolationMode::Bilinear => {
+                //  Blend the 4 image pixels whose centres surround the point, weighted by their distance
+                let (u, v) = (u - HALF, v - HALF);
+                let (col, row) = (u >> FRAC_BITS, v >> FRAC_BITS);
+                let one = 1 << WEIGHT_BITS;
+                let fx = (u >> (FRAC_BITS - WEIGHT_BITS)) & (one - 1);
+                let fy = (v >> (FRAC_BITS - WEIGHT_BITS)) & (one - 1);
+                let neighbours = [
+                    (col,     row,     (one - fx) * (one - fy)),
+                    (col + 1, row,     fx * (one - fy)),
+                    (col,     row + 1, (one - fx) * fy),
+                    (col + 1, row + 1, fx * fy),
+                ];
+                //  Sum the colours weighted by alpha, so that transparent pixels don't darken the colour
+                let mut sum = [0i64; 4];
+                for &(col, row, weight) in neighbours.iter() {
+                    if weight == 0 { continue; }
+                    let (color, alpha) = self.pixel(col, row);
+                    let weight = weight * alpha as i64;
+                    let (r, g, b) = expand(color);
+                    sum[0] += r * weight;
+                    sum[1] += g * weight;
+                    sum[2] += b * weight;
+                    sum[3] += weight;
                 }
+                if sum[3] == 0 { return TRANSPARENT; }
+                let channel = |c: usize| ((sum[c] + sum[3] / 2) / sum[3]) as u8;
+                let alpha = (sum[3] + (1 << (2 * WEIGHT_BITS - 1))) >> (2 * WEIGHT_BITS);
+                (Rgb565::from((channel(0), channel(1), channel(2))), alpha as u8)
             }
         }
-        Ok(image)
     }
 
-    fn draw_image(
-        &mut self,
-        image: &Self::Image,
-        rect: impl Into<Rect>,
-        interp: InterpolationMode,
-    ) {
-        let _ = self.with_save(|rc| {
-            let surface_pattern = SurfacePattern::create(image);
-            let filter = match interp {
-                InterpolationMode::NearestNeighbor => Filter::Nearest,
-                InterpolationMode::Bilinear => Filter::Bilinear,
-            };
-            surface_pattern.set_filter(filter);
-            let rect = rect.into();
-            rc.ctx.translate(rect.x0, rect.y0);
-            rc.ctx.scale(
-                rect.width() / (image.get_width() as f64),
-                rect.height() / (image.get_height() as f64),
-            );
-            rc.ctx.set_source(&surface_pattern);
-            rc.ctx.paint();
-            Ok(())
-        });
+    /// Return the colour and alpha of the image pixel, clamped to the edges of the image
+    fn pixel(&self, col: i64, row: i64) -> (Rgb565, u8) {
+        let (width, height) = (self.image.width() as i64, self.image.height() as i64);
+        let col = col.max(0).min(width - 1) as usize;
+        let row = row.max(0).min(height - 1) as usize;
+        decode(self.image.format(), self.image.row(row), col)
+    }
+}
+
+/// Return the red, green and blue components of the colour, expanded to 8 bits
+fn expand(color: Rgb565) -> (i64, i64, i64) {
+    let (r, g, b) = ((color.0 >> 11) as i64, (color.0 >> 5 & 0x3f) as i64, (color.0 & 0x1f) as i64);
+    ((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2))
+}
+
+/// Convert the pixel in column `col` of the image row to Rgb565 with alpha. Rgb565 and palette pixels are not converted.
+fn decode(format: ImageFormat, row: &[u8], col: usize) -> (Rgb565, u8) {
+    match format {
+        ImageFormat::Rgb => {
+            let pixel = &row[col * 3..col * 3 + 3];
+            (Rgb565::from((pixel[0], pixel[1], pixel[2])), 0xff)
+        }
+        ImageFormat::RgbaSeparate => {
+            let pixel = &row[col * 4..col * 4 + 4];
+            (Rgb565::from((pixel[0], pixel[1], pixel[2])), pixel[3])
+        }
+        ImageFormat::RgbaPremul => {
+            //  Divide the colour by the alpha
+            let pixel = &row[col * 4..col * 4 + 4];
+            let alpha = pixel[3];
+            if alpha == 0 { return TRANSPARENT; }
+            let unpremul = |c: u8| (c as u32 * 0xff / alpha as u32).min(0xff) as u8;
+            (Rgb565::from((unpremul(pixel[0]), unpremul(pixel[1]), unpremul(pixel[2]))), alpha)
+        }
+        ImageFormat::Rgb565Le => (Rgb565(u16::from_le_bytes([row[col * 2], row[col * 2 + 1]])), 0xff),
+        ImageFormat::Rgb565Be => (Rgb565(u16::from_be_bytes([row[col * 2], row[col * 2 + 1]])), 0xff),
+        ImageFormat::Grayscale8 => (Rgb565::from((row[col], row[col], row[col])), 0xff),
+        ImageFormat::Alpha8 => (Rgb565(0), row[col]),  //  Alpha masks are always black, as documented by piet
+        ImageFormat::Indexed8(palette) => lookup(palette, row[col]),
+        ImageFormat::Indexed4(palette) => {
+            //  First pixel is in the high 4 bits
+            let byte = row[col / 2];
+            lookup(palette, if col % 2 == 0 { byte >> 4 } else { byte & 0x0f })
+        }
+        _ => TRANSPARENT,  //  Unknown formats are transparent
+    }
+}
+
+/// Return the palette colour at the index. Indexes beyond the palette are transparent.
+fn lookup(palette: &[u16], index: u8) -> (Rgb565, u8) {
+    match palette.get(index as usize) {
+        Some(&color) => (Rgb565(color), 0xff),
+        None => TRANSPARENT,
     }
-*/ ////
\ No newline at end of file
+}
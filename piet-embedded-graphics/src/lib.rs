@@ -1,25 +1,47 @@
 //! The embedded-graphics backend for the Piet 2D graphics abstraction.
+//!
+//! `EmbedRenderContext` renders to any embedded-graphics draw target that implements
+//! `Drawing<Rgb565>`. The display driver for PineTime on Mynewt is in the separate
+//! `piet-embedded-mynewt` crate, which draws the pixels with `batch::draw_blocks()`.
 
 #![no_std]
 
-mod batch;
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+pub mod batch;
+mod blend;
 mod brush;
+mod clip;
 mod context;
-mod display;
+mod gradient;
+mod grapheme;
 mod image;
+mod path;
+mod raster;
 mod status;
+mod stroke;
 mod text;
+#[cfg(feature = "truetype")]
+mod truetype;
 
 #[cfg(test)]
 mod test;
 
 pub use context::EmbedRenderContext;
+pub use blend::{ AlphaFallback, ShadowBuffer };
 pub use brush::Brush;
+pub use clip::ClipMask;
 pub use text::{
+    BitmapFont,
+    DefaultTextCapacity,
     EmbedFont,
     EmbedFontBuilder,
     EmbedText,
     EmbedTextLayout,
     EmbedTextLayoutBuilder,
+    RenderFn,
 };
-pub use display::{ start_display, draw_to_display, set_display_pixels, show_touch };
\ No newline at end of file
+#[cfg(feature = "truetype")]
+pub use truetype::{ OutlineFont, ScaledFont };
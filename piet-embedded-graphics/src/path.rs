@@ -0,0 +1,77 @@
+//! Flatten the Bézier curves in a path into line segments, so that the path may be rasterized.
+use piet::kurbo::{PathEl, Point};
+
+/// Max number of line segments for each curve
+const MAX_SEGMENTS: f64 = 100.;
+
+/// Flatten the path into `MoveTo`, `LineTo` and `ClosePath` elements, which are passed to the callback.
+/// Quadratic and cubic Bézier curves are subdivided into line segments that deviate from the curve
+/// by at most `tolerance`.
+pub fn flatten<I: IntoIterator<Item = PathEl>>(path: I, tolerance: f64, mut callback: impl FnMut(PathEl)) {
+    //  Start of the current subpath, and the last point
+    let mut start = Point::ZERO;
+    let mut last = Point::ZERO;
+    for el in path {
+        match el {
+            PathEl::MoveTo(p) => {
+                callback(PathEl::MoveTo(p));
+                start = p;
+                last = p;
+            }
+            PathEl::LineTo(p) => {
+                callback(PathEl::LineTo(p));
+                last = p;
+            }
+            PathEl::QuadTo(p1, p2) => {
+                //  The error of a uniform subdivision is |p0 - 2 p1 + p2| / (4 n^2)
+                let dd = second_difference(last, p1, p2);
+                let n = num_segments(dd / 4., tolerance);
+                for i in 1..n {
+                    let t = i as f64 / n as f64;
+                    let mt = 1. - t;
+                    callback(PathEl::LineTo(Point::new(
+                        mt * mt * last.x + 2. * mt * t * p1.x + t * t * p2.x,
+                        mt * mt * last.y + 2. * mt * t * p1.y + t * t * p2.y,
+                    )));
+                }
+                callback(PathEl::LineTo(p2));
+                last = p2;
+            }
+            PathEl::CurveTo(p1, p2, p3) => {
+                //  The error of a uniform subdivision is at most 3/4 max |p(i) - 2 p(i+1) + p(i+2)| / n^2
+                let dd = second_difference(last, p1, p2).max(second_difference(p1, p2, p3));
+                let n = num_segments(dd * 0.75, tolerance);
+                for i in 1..n {
+                    let t = i as f64 / n as f64;
+                    let mt = 1. - t;
+                    let (a, b, c, d) = (mt * mt * mt, 3. * mt * mt * t, 3. * mt * t * t, t * t * t);
+                    callback(PathEl::LineTo(Point::new(
+                        a * last.x + b * p1.x + c * p2.x + d * p3.x,
+                        a * last.y + b * p1.y + c * p2.y + d * p3.y,
+                    )));
+                }
+                callback(PathEl::LineTo(p3));
+                last = p3;
+            }
+            PathEl::ClosePath => {
+                //  A curve after the subpath is closed starts from the start of the subpath
+                callback(PathEl::ClosePath);
+                last = start;
+            }
+        }
+    }
+}
+
+/// Return the length of the second difference p0 - 2 p1 + p2, which bounds the curvature
+fn second_difference(p0: Point, p1: Point, p2: Point) -> f64 {
+    let x = p0.x - 2. * p1.x + p2.x;
+    let y = p0.y - 2. * p1.y + p2.y;
+    libm::sqrt(x * x + y * y)
+}
+
+/// Return the number of line segments needed to keep the error `err / n^2` within the tolerance
+fn num_segments(err: f64, tolerance: f64) -> usize {
+    let n = libm::ceil(libm::sqrt(err / tolerance));
+    if n >= 1. { n.min(MAX_SEGMENTS) as usize }
+    else { 1 }
+}
@@ -0,0 +1,500 @@
+//! Scanline rasterizer that converts the outline of a shape into spans of pixels, without dynamic memory allocation.
+//! Coordinates are converted to 24.8 fixed-point and each pixel row is sampled at the pixel centre.
+//! To keep the memory usage bounded, the rows are rasterized in bands: only the edges that cross the
+//! current band are kept, and the band is split into smaller bands if there are too many edges.
+//! If a single row has too many edges, its crossings are found in several passes from left to right,
+//! keeping the winding number between passes.
+//! The anti-aliased rasterizer samples each row at several sub-scanlines and accumulates the coverage
+//! of each pixel in a fixed-size row buffer.
+use piet::kurbo::{Affine, PathEl, Point, Rect, Shape};
+use crate::path;
+
+/// Max number of edges per band, and max number of crossings per pass over a row
+type MaxEdges = heapless::consts::U64;
+
+/// Number of fractional bits for fixed-point coordinates
+const FRAC_BITS: i32 = 8;
+/// 1.0 in fixed-point
+const ONE: i32 = 1 << FRAC_BITS;
+/// 0.5 in fixed-point, for sampling at the pixel centre
+const HALF: i32 = ONE / 2;
+/// Max magnitude of fixed-point coordinates
+const MAX_FIXED: i32 = 1 << 30;
+/// Number of sub-scanlines per row for anti-aliasing
+const SUBSAMPLES: i32 = 4;
+/// Max number of columns for anti-aliasing. Columns beyond are not rendered.
+const MAX_COVER_WIDTH: usize = 256;
+
+/// Source of the line segments that outline a shape. The segments may be requested more than once.
+pub trait Outline {
+    /// Call `f` with the start and end points (in pixel coordinates) of each line segment in the outline
+    fn for_each_line<F: FnMut(Point, Point)>(&self, f: F);
+}
+
+/// Outline of a kurbo shape, flattened and transformed to pixel coordinates. Each subpath is closed.
+pub struct ShapeOutline<'s, S: Shape> {
+    /// Shape to be outlined
+    shape:     &'s S,
+    /// Transform from the shape coordinates to pixel coordinates
+    transform: Affine,
+    /// Max distance between the curves and the line segments, in shape coordinates
+    tolerance: f64,
+}
+
+impl<'s, S: Shape> ShapeOutline<'s, S> {
+    /// Create the outline of the shape, transformed to pixel coordinates
+    pub fn new(shape: &'s S, transform: Affine, tolerance: f64) -> Self {
+        ShapeOutline { shape, transform, tolerance }
+    }
+}
+
+impl<'s, S: Shape> Outline for ShapeOutline<'s, S> {
+    fn for_each_line<F: FnMut(Point, Point)>(&self, mut f: F) {
+        let transform = self.transform;
+        //  Start and last point of the current subpath
+        let mut start: Option<Point> = None;
+        let mut last = Point::ZERO;
+        path::flatten(self.shape.to_bez_path(self.tolerance), self.tolerance, |el| {
+            match el {
+                PathEl::MoveTo(p) => {
+                    //  Close the previous subpath
+                    if let Some(s) = start { f(last, s); }
+                    let p = transform * p;
+                    start = Some(p);
+                    last = p;
+                }
+                PathEl::LineTo(p) => {
+                    let p = transform * p;
+                    if start.is_none() { start = Some(last); }
+                    f(last, p);
+                    last = p;
+                }
+                PathEl::ClosePath => {
+                    if let Some(s) = start { f(last, s); last = s; }
+                    start = None;
+                }
+                _ => {}  //  Curves have been flattened
+            }
+        });
+        if let Some(s) = start { f(last, s); }
+    }
+}
+
+/// Rule for deciding whether a pixel is inside a shape, based on the winding number of the outline around the pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    /// Inside if the winding number is not zero
+    NonZero,
+    /// Inside if the winding number is odd
+    EvenOdd,
+}
+
+impl FillRule {
+    /// Return true if the winding number is inside the shape
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding & 1 != 0,
+        }
+    }
+}
+
+/// A horizontal run of pixels on the same row, with the same coverage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    /// Row number
+    pub y:     i32,
+    /// Start column number
+    pub x0:    i32,
+    /// End column number (exclusive)
+    pub x1:    i32,
+    /// Coverage of the pixels by the shape, 255 if fully covered
+    pub alpha: u8,
+}
+
+/// Edge of the outline, oriented from top to bottom
+#[derive(Clone, Copy)]
+struct Edge {
+    /// Column at the top of the edge, in fixed-point
+    x:     i32,
+    /// Top row of the edge, in fixed-point
+    y_top: i32,
+    /// Bottom row of the edge (exclusive), in fixed-point
+    y_bot: i32,
+    /// Change in column per row, in 16.16 fixed-point
+    dxdy:  i32,
+    /// +1 if the edge runs downwards, -1 if upwards
+    dir:   i8,
+}
+
+impl Edge {
+    /// Create the edge for the line segment from `p0` to `p1` (in pixel coordinates), or `None` if it's horizontal
+    fn new(p0: Point, p1: Point) -> Option<Edge> {
+        let (x0, y0, x1, y1) = (to_fixed(p0.x), to_fixed(p0.y), to_fixed(p1.x), to_fixed(p1.y));
+        if y0 == y1 { return None; }  //  Horizontal edges don't cross any rows
+        let (x_top, y_top, x_bot, y_bot, dir) =
+            if y0 < y1 { (x0, y0, x1, y1, 1) }
+            else       { (x1, y1, x0, y0, -1) };
+        let dxdy = ((x_bot as i64 - x_top as i64) << 16) / (y_bot as i64 - y_top as i64);
+        Some(Edge {
+            x: x_top,
+            y_top,
+            y_bot,
+            dxdy: dxdy.max(i32::min_value() as i64).min(i32::max_value() as i64) as i32,
+            dir,
+        })
+    }
+
+    /// Return the crossing of the edge with the sampled row `sample` (in fixed-point), or `None` if the edge doesn't cross it.
+    /// `index` orders the crossings that have the same column.
+    fn crossing(&self, sample: i32, index: u32) -> Option<Crossing> {
+        if sample < self.y_top || sample >= self.y_bot { return None; }
+        let x = self.x + (((sample - self.y_top) as i64 * self.dxdy as i64) >> 16) as i32;
+        Some(Crossing { x, dir: self.dir, index })
+    }
+}
+
+/// Point where an edge crosses the sampled row
+#[derive(Clone, Copy)]
+struct Crossing {
+    /// Column of the crossing, in fixed-point
+    x:     i32,
+    /// +1 if the edge runs downwards, -1 if upwards
+    dir:   i8,
+    /// Index of the edge in the outline
+    index: u32,
+}
+
+impl Crossing {
+    /// Key that sorts the crossings from left to right
+    fn key(&self) -> (i32, u32) {
+        (self.x, self.index)
+    }
+}
+
+/// Iterator that returns the spans of pixels inside the outline, row by row from top to bottom.
+/// Only the pixels whose centres lie inside `bounds` are returned.
+pub struct Rasterizer<'o, O: Outline> {
+    /// Outline to be rasterized
+    outline:   &'o O,
+    /// Fill rule for the outline
+    rule:      FillRule,
+    /// First column to be rasterized
+    x_min:     i32,
+    /// Last column to be rasterized (exclusive)
+    x_max:     i32,
+    /// Last row to be rasterized (exclusive)
+    y_max:     i32,
+    /// Next row to be rasterized
+    y:         i32,
+    /// Row of the crossings being returned as spans
+    row:       i32,
+    /// Last row of the current band (exclusive)
+    band_end:  i32,
+    /// Number of rows per band
+    band_rows: i32,
+    /// Number of sub-scanlines sampled per row
+    subsamples: i32,
+    /// Edges that cross the current band
+    edges:     heapless::Vec<Edge, MaxEdges>,
+    /// True if the current band is a single row with too many edges, so its crossings are found in passes
+    overflow:  bool,
+    /// Sampled row of the crossings, in fixed-point
+    sample:    i32,
+    /// Crossings for the current row (or for the current pass over the row), sorted by column
+    crossings: heapless::Vec<Crossing, MaxEdges>,
+    /// Next crossing to be returned as a span
+    next:      usize,
+    /// Winding number before the next crossing
+    winding:   i32,
+    /// Column where the current interval inside the outline starts, in fixed-point
+    start:     i32,
+}
+
+impl<'o, O: Outline> Rasterizer<'o, O> {
+    /// Create a rasterizer for the outline with the fill rule, clipped to the pixels whose centres lie inside `bounds`
+    pub fn new(outline: &'o O, rule: FillRule, bounds: Rect) -> Self {
+        Self::with_subsamples(outline, rule, bounds, 1)
+    }
+
+    /// Create a rasterizer that samples each row at `subsamples` sub-scanlines
+    fn with_subsamples(outline: &'o O, rule: FillRule, bounds: Rect, subsamples: i32) -> Self {
+        //  Find the rows covered by the outline
+        let mut y_top = MAX_FIXED;
+        let mut y_bot = -MAX_FIXED;
+        outline.for_each_line(|p0, p1| {
+            let (y0, y1) = (to_fixed(p0.y), to_fixed(p1.y));
+            y_top = y_top.min(y0.min(y1));
+            y_bot = y_bot.max(y0.max(y1));
+        });
+        //  A row is covered if its last sub-scanline is below the top and its first is above the bottom
+        let first_offset = sample_offset(0, subsamples);
+        let last_offset  = sample_offset(subsamples - 1, subsamples);
+        let y_min = to_pixel(to_fixed(bounds.y0)).max((y_top - last_offset + ONE - 1) >> FRAC_BITS);
+        let y_max = to_pixel(to_fixed(bounds.y1)).min((y_bot - first_offset + ONE - 1) >> FRAC_BITS);
+        Rasterizer {
+            outline,
+            rule,
+            x_min:     to_pixel(to_fixed(bounds.x0)),
+            x_max:     to_pixel(to_fixed(bounds.x1)),
+            y_max,
+            y:         y_min,
+            row:       y_min,
+            band_end:  y_min,
+            band_rows: (y_max - y_min).max(1),
+            subsamples,
+            edges:     heapless::Vec::new(),
+            overflow:  false,
+            sample:    0,
+            crossings: heapless::Vec::new(),
+            next:      0,
+            winding:   0,
+            start:     0,
+        }
+    }
+
+    /// Collect the edges that cross the current band. If there are too many edges, split the band.
+    fn next_band(&mut self) {
+        loop {
+            let band_end = (self.y + self.band_rows).min(self.y_max);
+            let fits = self.collect_edges(self.y, band_end);
+            if fits || self.band_rows == 1 {
+                //  If there are too many edges for a single row, the crossings are found in passes
+                self.overflow = !fits;
+                self.band_end = band_end;
+                self.band_rows = (self.band_rows * 2).min(self.y_max - band_end).max(1);
+                return;
+            }
+            self.band_rows /= 2;
+        }
+    }
+
+    /// Collect the edges that cross the rows from `y0` to `y1` (exclusive). Return false if there are too many edges.
+    fn collect_edges(&mut self, y0: i32, y1: i32) -> bool {
+        let first_sample = (y0 << FRAC_BITS) + sample_offset(0, self.subsamples);
+        let last_sample  = ((y1 - 1) << FRAC_BITS) + sample_offset(self.subsamples - 1, self.subsamples);
+        let edges = &mut self.edges;
+        let mut fits = true;
+        edges.clear();
+        self.outline.for_each_line(|p0, p1| {
+            let edge = match Edge::new(p0, p1) { Some(edge) => edge, None => return };
+            if edge.y_bot <= first_sample || edge.y_top > last_sample { return; }  //  Edge is outside the band
+            if edges.push(edge).is_err() { fits = false; }
+        });
+        fits
+    }
+
+    /// Compute the crossings of the edges with the sampled row `sample` (in fixed-point), sorted by column
+    fn collect_crossings(&mut self, row: i32, sample: i32) {
+        self.row = row;
+        self.sample = sample;
+        self.winding = 0;
+        self.start = 0;
+        if self.overflow {
+            self.collect_next_crossings(None);
+            return;
+        }
+        self.crossings.clear();
+        self.next = 0;
+        for (index, edge) in self.edges.iter().enumerate() {
+            if let Some(crossing) = edge.crossing(sample, index as u32) {
+                //  Never fails because there are as many crossings as edges
+                self.crossings.push(crossing).ok();
+            }
+        }
+        self.crossings.sort_unstable_by_key(Crossing::key);
+    }
+
+    /// Compute the leftmost crossings of the outline with the sampled row that are after the crossing with the
+    /// key `after`, sorted by column. This is one pass over a row that has too many edges for the band.
+    fn collect_next_crossings(&mut self, after: Option<(i32, u32)>) {
+        let sample = self.sample;
+        let crossings = &mut self.crossings;
+        let mut index = 0;
+        crossings.clear();
+        self.next = 0;
+        self.outline.for_each_line(|p0, p1| {
+            let crossing = Edge::new(p0, p1).and_then(|edge| edge.crossing(sample, index));
+            index += 1;
+            let crossing = match crossing { Some(crossing) => crossing, None => return };
+            if after.map_or(false, |after| crossing.key() <= after) { return; }  //  Returned in a previous pass
+            //  Keep the leftmost crossings, sorted by column
+            if crossings.len() == crossings.capacity() {
+                if crossings.last().map_or(false, |last| crossing.key() > last.key()) { return; }
+                crossings.pop();
+            }
+            crossings.push(crossing).ok();
+            let mut i = crossings.len() - 1;
+            while i > 0 && crossings[i - 1].key() > crossings[i].key() {
+                crossings.swap(i - 1, i);
+                i -= 1;
+            }
+        });
+    }
+
+    /// Return the next interval inside the outline on the sampled row, from the start column to the end column
+    /// (exclusive, in fixed-point)
+    fn next_interval(&mut self) -> Option<(i32, i32)> {
+        loop {
+            while self.next < self.crossings.len() {
+                let Crossing { x, dir, .. } = self.crossings[self.next];
+                self.next += 1;
+                let was_inside = self.rule.is_inside(self.winding);
+                self.winding += dir as i32;
+                let is_inside = self.rule.is_inside(self.winding);
+                if !was_inside && is_inside {
+                    self.start = x;  //  Entering the shape
+                } else if was_inside && !is_inside {
+                    return Some((self.start, x));  //  Leaving the shape
+                }
+            }
+            //  If the row has more crossings than the last pass returned, continue after the last crossing
+            if !self.overflow || self.crossings.len() < self.crossings.capacity() { return None; }
+            let after = self.crossings.last().map(Crossing::key);
+            self.collect_next_crossings(after);
+        }
+    }
+}
+
+impl<'o, O: Outline> Iterator for Rasterizer<'o, O> {
+    /// This Iterator returns spans of pixels
+    type Item = Span;
+
+    /// Return the next span of pixels inside the outline
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            //  Return the next span between the crossings of the current row, with the pixels whose centres are inside.
+            while let Some((start, end)) = self.next_interval() {
+                let x0 = to_pixel(start).max(self.x_min);
+                let x1 = to_pixel(end).min(self.x_max);
+                if x0 < x1 {
+                    return Some(Span { y: self.row, x0, x1, alpha: 0xff });
+                }
+            }
+            //  Move to the next row.
+            if self.y >= self.y_max { return None; }
+            if self.y >= self.band_end { self.next_band(); }
+            let row = self.y;
+            self.y += 1;
+            self.collect_crossings(row, (row << FRAC_BITS) + HALF);
+        }
+    }
+}
+
+/// Iterator that returns the spans of pixels covered by the outline, row by row from top to bottom,
+/// with the coverage of each pixel as alpha. Only the pixels whose centres lie inside `bounds` are returned.
+pub struct AntialiasRasterizer<'o, O: Outline> {
+    /// Rasterizer that samples the sub-scanlines
+    raster: Rasterizer<'o, O>,
+    /// Change in coverage from the previous column, for each column of the current row
+    cover:  [i16; MAX_COVER_WIDTH + 2],
+    /// Next column to be returned as a span, relative to the first column
+    x:      usize,
+    /// Last column with a change in coverage (exclusive), relative to the first column
+    end:    usize,
+    /// Coverage of the previous column
+    alpha:  i32,
+}
+
+impl<'o, O: Outline> AntialiasRasterizer<'o, O> {
+    /// Create an anti-aliased rasterizer for the outline with the fill rule, clipped to the pixels whose centres lie inside `bounds`
+    pub fn new(outline: &'o O, rule: FillRule, bounds: Rect) -> Self {
+        let mut raster = Rasterizer::with_subsamples(outline, rule, bounds, SUBSAMPLES);
+        raster.x_max = raster.x_max.min(raster.x_min + MAX_COVER_WIDTH as i32);
+        AntialiasRasterizer {
+            raster,
+            cover: [0; MAX_COVER_WIDTH + 2],
+            x:     0,
+            end:   0,
+            alpha: 0,
+        }
+    }
+
+    /// Accumulate the coverage of the pixels in the row from each sub-scanline
+    fn accumulate_row(&mut self, row: i32) {
+        let (x_left, x_right) = (self.raster.x_min << FRAC_BITS, self.raster.x_max << FRAC_BITS);
+        let mut first = MAX_COVER_WIDTH + 1;
+        let mut last = 0;
+        for k in 0..SUBSAMPLES {
+            let raster = &mut self.raster;
+            raster.collect_crossings(row, (row << FRAC_BITS) + sample_offset(k, SUBSAMPLES));
+            //  Add the coverage of each interval inside the outline, clipped to the columns
+            while let Some((start, end)) = raster.next_interval() {
+                let x0 = start.max(x_left) - x_left;
+                let x1 = end.min(x_right) - x_left;
+                if x0 >= x1 { continue; }
+                let (x0, x1) = (x0 as usize, x1 as usize);
+                add_interval(&mut self.cover, x0, x1);
+                first = first.min(x0 >> FRAC_BITS);
+                last = last.max((x1 >> FRAC_BITS) + 2);
+            }
+        }
+        self.x = first;
+        self.end = last.min(MAX_COVER_WIDTH + 2);
+        self.alpha = 0;
+    }
+}
+
+impl<'o, O: Outline> Iterator for AntialiasRasterizer<'o, O> {
+    /// This Iterator returns spans of pixels
+    type Item = Span;
+
+    /// Return the next span of pixels covered by the outline
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            //  Return the next run of pixels with the same coverage in the current row, and clear the coverage.
+            while self.x < self.end {
+                let x0 = self.x;
+                self.alpha += self.cover[x0] as i32;
+                self.cover[x0] = 0;
+                self.x += 1;
+                while self.x < self.end && self.cover[self.x] == 0 { self.x += 1; }
+                let alpha = self.alpha.max(0).min(0xff) as u8;
+                let x1 = self.x.min(MAX_COVER_WIDTH);
+                if alpha > 0 && x0 < x1 {
+                    let x_min = self.raster.x_min;
+                    return Some(Span { y: self.raster.row, x0: x_min + x0 as i32, x1: x_min + x1 as i32, alpha });
+                }
+            }
+            //  Move to the next row.
+            let raster = &mut self.raster;
+            if raster.y >= raster.y_max { return None; }
+            if raster.y >= raster.band_end { raster.next_band(); }
+            let row = raster.y;
+            raster.y += 1;
+            self.accumulate_row(row);
+        }
+    }
+}
+
+/// Add the coverage of one sub-scanline from `x0` to `x1` (relative fixed-point columns) to the changes in coverage
+fn add_interval(cover: &mut [i16], x0: usize, x1: usize) {
+    //  Each sub-scanline covers a full pixel with this coverage
+    let full = 0x100 / SUBSAMPLES;
+    let (p0, f0) = (x0 >> FRAC_BITS, (x0 & (ONE as usize - 1)) as i32);
+    let (p1, f1) = (x1 >> FRAC_BITS, (x1 & (ONE as usize - 1)) as i32);
+    //  Partial coverage of the first pixel, then full coverage until the last pixel.
+    //  The two parts add up to the full coverage, so that no coverage is lost to rounding.
+    let (c0, c1) = ((full * f0) >> FRAC_BITS, (full * f1) >> FRAC_BITS);
+    cover[p0]     += (full - c0) as i16;
+    cover[p0 + 1] += c0 as i16;
+    //  Partial coverage of the last pixel, then no coverage
+    cover[p1]     -= (full - c1) as i16;
+    cover[p1 + 1] -= c1 as i16;
+}
+
+/// Return the offset of sub-scanline `k` from the top of the row, in fixed-point
+fn sample_offset(k: i32, subsamples: i32) -> i32 {
+    (2 * k + 1) * ONE / (2 * subsamples)
+}
+
+/// Convert the coordinate to fixed-point
+fn to_fixed(x: f64) -> i32 {
+    //  Clamp to keep the fixed-point arithmetic within range
+    libm::round(x * ONE as f64).max(-MAX_FIXED as f64).min(MAX_FIXED as f64) as i32
+}
+
+/// Return the first pixel whose centre is at or after the fixed-point coordinate
+fn to_pixel(x: i32) -> i32 {
+    (x - HALF + ONE - 1) >> FRAC_BITS
+}
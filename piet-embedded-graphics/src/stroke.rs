@@ -0,0 +1,411 @@
+//! Convert stroked paths into outlines that may be filled by the rasterizer. Each line segment of the flattened
+//! path is widened into a rectangle, and the joins and caps are added as convex polygons. The polygons are all
+//! oriented the same way, so that filling the outline with the nonzero rule renders each pixel only once.
+//! Dashed strokes are split into dashes along the flattened path, and each dash is capped like an open subpath.
+use piet::kurbo::{Affine, PathEl, Point, Shape, Vec2};
+use piet::{LineCap, LineJoin, StrokeStyle};
+use crate::{path, raster::Outline};
+
+/// Miter limit if the stroke style doesn't specify one, same as Cairo
+const DEFAULT_MITER_LIMIT: f64 = 10.;
+/// Max number of line segments for each round join or cap
+const MAX_ARC_SEGMENTS: f64 = 64.;
+/// Line segments shorter than this (in pixels) have no direction and are skipped
+const MIN_LENGTH: f64 = 1e-6;
+/// Max number of dashes in a dash pattern, same as piet
+const MAX_DASHES: usize = 5;
+/// Dash patterns with a shorter period (in pixels) can't be seen, so the stroke is solid
+const MIN_DASH_PERIOD: f64 = 1. / 16.;
+
+/// Dash pattern in pixel coordinates. Only the first `MAX_DASHES` lengths of a pattern are used,
+/// which is as many as the dash array of piet's `StrokeStyle` holds.
+#[derive(Clone, Copy, Debug)]
+struct Dashes {
+    /// Lengths of the dashes and gaps, alternating. Odd patterns are repeated to make them even.
+    lengths: [f64; MAX_DASHES * 2],
+    /// Number of lengths
+    count:   usize,
+    /// Distance into the pattern where each subpath starts
+    offset:  f64,
+}
+
+/// Position in the dash pattern
+#[derive(Clone, Copy, Debug)]
+struct DashState {
+    /// Index of the current dash or gap
+    index:     usize,
+    /// Length remaining in the current dash or gap
+    remaining: f64,
+    /// True for a dash, false for a gap
+    on:        bool,
+}
+
+impl DashState {
+    /// Position for solid strokes, which are never split
+    const SOLID: DashState = DashState { index: 0, remaining: core::f64::INFINITY, on: true };
+}
+
+impl Dashes {
+    /// Create the dash pattern with the lengths and offset (in user coordinates), scaled to pixels.
+    /// Lengths after the first `MAX_DASHES` are ignored.
+    /// Return None if the pattern is empty, invalid or shorter than `MIN_DASH_PERIOD`, so that the stroke is solid.
+    fn new(dashes: &[f64], offset: f64, scale: f64) -> Option<Self> {
+        let len = dashes.len().min(MAX_DASHES);
+        if len == 0 || dashes[..len].iter().any(|&dash| !(dash >= 0.)) { return None; }
+        let count = if len % 2 == 1 { len * 2 } else { len };
+        let mut lengths = [0.; MAX_DASHES * 2];
+        for (i, length) in lengths[..count].iter_mut().enumerate() {
+            *length = dashes[i % len] * scale;
+        }
+        let period: f64 = lengths[..count].iter().sum();
+        if !(period >= MIN_DASH_PERIOD && period.is_finite()) { return None; }
+        //  Offset is wrapped into the first period
+        let offset = libm::fmod(offset * scale, period);
+        let offset = if offset < 0. { offset + period } else { offset };
+        Some(Dashes { lengths, count, offset })
+    }
+
+    /// Return the position in the dash pattern at the start of a subpath
+    fn start(&self) -> DashState {
+        let mut index = 0;
+        let mut offset = self.offset;
+        while offset > 0. && offset >= self.lengths[index] {
+            offset -= self.lengths[index];
+            index = (index + 1) % self.count;
+        }
+        DashState { index, remaining: self.lengths[index] - offset, on: index % 2 == 0 }
+    }
+
+    /// Return the position at the start of the next dash or gap
+    fn next(&self, state: DashState) -> DashState {
+        let index = (state.index + 1) % self.count;
+        DashState { index, remaining: self.lengths[index], on: index % 2 == 0 }
+    }
+}
+
+/// Stroke parameters in pixel coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct Style {
+    /// Half the stroke width
+    half_width:  f64,
+    /// How the line segments are joined
+    join:        LineJoin,
+    /// How the ends of open subpaths are capped
+    cap:         LineCap,
+    /// Max ratio of the miter length to the stroke width, beyond which miter joins are bevelled
+    miter_limit: f64,
+    /// Max distance between the round joins and caps and their line segments
+    tolerance:   f64,
+    /// Dash pattern, or None for solid strokes
+    dashes:      Option<Dashes>,
+}
+
+impl Style {
+    /// Create the stroke parameters for the width and the optional stroke style (in user coordinates),
+    /// scaled to pixels. Round joins and caps are flattened with the tolerance (in pixels).
+    pub fn new(width: f64, style: Option<&StrokeStyle>, scale: f64, tolerance: f64) -> Self {
+        Style {
+            half_width:  (width * scale).max(0.) / 2.,
+            join:        style.and_then(|style| style.line_join).unwrap_or(LineJoin::Miter),
+            cap:         style.and_then(|style| style.line_cap).unwrap_or(LineCap::Butt),
+            miter_limit: style.and_then(|style| style.miter_limit).unwrap_or(DEFAULT_MITER_LIMIT),
+            tolerance,
+            dashes:      style
+                .and_then(|style| style.dash.as_ref())
+                .and_then(|(dashes, offset)| Dashes::new(dashes, *offset, scale)),
+        }
+    }
+
+    /// Widen the stroke to at least `width` pixels
+    pub fn with_min_width(mut self, width: f64) -> Self {
+        self.half_width = self.half_width.max(width / 2.);
+        self
+    }
+}
+
+/// Outline of a stroked kurbo shape, flattened and transformed to pixel coordinates
+pub struct StrokeOutline<'s, S: Shape> {
+    /// Shape to be stroked
+    shape:     &'s S,
+    /// Transform from the shape coordinates to pixel coordinates
+    transform: Affine,
+    /// Max distance between the curves and the line segments, in shape coordinates
+    tolerance: f64,
+    /// Stroke parameters in pixel coordinates
+    style:     Style,
+}
+
+impl<'s, S: Shape> StrokeOutline<'s, S> {
+    /// Create the outline of the shape stroked with the style, transformed to pixel coordinates
+    pub fn new(shape: &'s S, transform: Affine, tolerance: f64, style: Style) -> Self {
+        StrokeOutline { shape, transform, tolerance, style }
+    }
+}
+
+impl<'s, S: Shape> Outline for StrokeOutline<'s, S> {
+    fn for_each_line<F: FnMut(Point, Point)>(&self, mut f: F) {
+        let transform = self.transform;
+        let mut stroker = Stroker::new(self.style);
+        path::flatten(self.shape.to_bez_path(self.tolerance), self.tolerance, |el| {
+            match el {
+                PathEl::MoveTo(p) => stroker.move_to(transform * p, &mut f),
+                PathEl::LineTo(p) => stroker.line_to(transform * p, &mut f),
+                PathEl::ClosePath => stroker.close(&mut f),
+                _ => {}  //  Curves have been flattened
+            }
+        });
+        stroker.finish(&mut f);
+    }
+}
+
+/// Converts the flattened subpaths of a path into the polygons of the stroke, one element at a time.
+/// Dashed subpaths are split into pieces, one for each dash.
+pub struct Stroker {
+    /// Stroke parameters
+    style:       Style,
+    /// Start point of the current subpath
+    start:       Point,
+    /// Last point of the current subpath
+    last:        Point,
+    /// Start point of the current piece
+    piece_start: Point,
+    /// Direction of the first line segment of the current piece
+    first_dir:   Option<Vec2>,
+    /// Direction of the last line segment of the current piece
+    last_dir:    Option<Vec2>,
+    /// Direction of the last line segment seen, for capping pieces with zero length
+    hint_dir:    Vec2,
+    /// True if the current piece has been drawn and needs caps at its ends
+    open:        bool,
+    /// True if the current piece starts at the start point of the subpath
+    head:        bool,
+    /// Direction of the first line segment of the first piece, if its start cap is deferred until the subpath is closed
+    head_dir:    Option<Vec2>,
+    /// Position in the dash pattern
+    dash:        DashState,
+}
+
+impl Stroker {
+    /// Create a stroker with the stroke parameters
+    pub fn new(style: Style) -> Self {
+        Stroker {
+            style,
+            start:       Point::ZERO,
+            last:        Point::ZERO,
+            piece_start: Point::ZERO,
+            first_dir:   None,
+            last_dir:    None,
+            hint_dir:    Vec2::new(1., 0.),
+            open:        false,
+            head:        true,
+            head_dir:    None,
+            dash:        style.dashes.map_or(DashState::SOLID, |dashes| dashes.start()),
+        }
+    }
+
+    /// Start a new subpath at the point
+    pub fn move_to<F: FnMut(Point, Point)>(&mut self, p: Point, f: &mut F) {
+        self.finish(f);
+        self.start = p;
+        self.last = p;
+        self.piece_start = p;
+        self.head = true;
+        self.dash = self.style.dashes.map_or(DashState::SOLID, |dashes| dashes.start());
+    }
+
+    /// Add a line segment from the last point to the point. The line segment is split into dashes if the stroke is dashed.
+    pub fn line_to<F: FnMut(Point, Point)>(&mut self, p: Point, f: &mut F) {
+        let dashes = match self.style.dashes {
+            Some(dashes) => dashes,
+            None => { self.segment_to(p, f); return; }
+        };
+        let p0 = self.last;
+        let d = p - p0;
+        let len = d.hypot();
+        if len >= MIN_LENGTH { self.hint_dir = d / len; }
+        let mut pos = 0.;
+        //  Split the line segment wherever a dash starts or ends
+        while self.dash.remaining <= len - pos {
+            pos += self.dash.remaining;
+            let q = if len >= MIN_LENGTH { p0 + d * (pos / len) } else { p };
+            if self.dash.on {
+                self.segment_to(q, f);
+                self.end_piece(f);
+            } else {
+                self.start_piece(q);
+            }
+            self.dash = dashes.next(self.dash);
+        }
+        self.dash.remaining -= len - pos;
+        if self.dash.on { self.segment_to(p, f); }
+        else { self.last = p; }
+    }
+
+    /// Close the current subpath with a line segment back to the start point, joined to the first line segment
+    pub fn close<F: FnMut(Point, Point)>(&mut self, f: &mut F) {
+        self.line_to(self.start, f);
+        if self.open {
+            let head_dir = if self.head { self.first_dir } else { self.head_dir };
+            if let (Some(last_dir), Some(head_dir)) = (self.last_dir, head_dir) {
+                //  The stroke continues through the start point, so the last piece is joined to the first piece
+                self.join(self.start, last_dir, head_dir, f);
+                if let (false, Some(first_dir)) = (self.head, self.first_dir) {
+                    self.cap(self.piece_start, -first_dir, f);
+                }
+                self.head_dir = None;
+                self.open = false;
+                self.first_dir = None;
+                self.last_dir = None;
+            }
+        }
+        //  Continue from the start point with a new subpath
+        let start = self.start;
+        self.move_to(start, f);
+    }
+
+    /// End the current subpath, with caps at the ends of the last piece and the first piece
+    pub fn finish<F: FnMut(Point, Point)>(&mut self, f: &mut F) {
+        self.end_piece(f);
+        if let Some(head_dir) = self.head_dir.take() {
+            self.cap(self.start, -head_dir, f);
+        }
+    }
+
+    /// Add a line segment of the current piece from the last point to the point, joined to the previous line segment
+    fn segment_to<F: FnMut(Point, Point)>(&mut self, p: Point, f: &mut F) {
+        self.open = true;
+        let d = p - self.last;
+        let len = d.hypot();
+        if len < MIN_LENGTH { return; }
+        let dir = d / len;
+        match self.last_dir {
+            Some(last_dir) => self.join(self.last, last_dir, dir, f),
+            None => self.first_dir = Some(dir),
+        }
+        let n = perp(dir) * self.style.half_width;
+        convex(&[self.last + n, p + n, p - n, self.last - n], f);
+        self.last = p;
+        self.last_dir = Some(dir);
+        self.hint_dir = dir;
+    }
+
+    /// Start a new piece at the point, where a dash starts
+    fn start_piece(&mut self, p: Point) {
+        self.last = p;
+        self.piece_start = p;
+        self.head = false;
+        self.open = true;
+    }
+
+    /// End the current piece, with caps at both ends if the piece was drawn. The start cap of the first piece
+    /// is deferred, in case the subpath is closed.
+    fn end_piece<F: FnMut(Point, Point)>(&mut self, f: &mut F) {
+        if self.open {
+            match (self.first_dir, self.last_dir) {
+                (Some(first_dir), Some(last_dir)) => {
+                    if self.head { self.head_dir = Some(first_dir); }
+                    else { self.cap(self.piece_start, -first_dir, f); }
+                    self.cap(self.last, last_dir, f);
+                }
+                _ => {
+                    //  Piece has zero length: draw a dot with the caps, like Cairo
+                    self.cap(self.piece_start, -self.hint_dir, f);
+                    self.cap(self.piece_start, self.hint_dir, f);
+                }
+            }
+        }
+        self.open = false;
+        self.head = false;
+        self.first_dir = None;
+        self.last_dir = None;
+    }
+
+    /// Join the line segments with directions `d0` and `d1` at the point, on the outer side of the turn
+    fn join<F: FnMut(Point, Point)>(&self, p: Point, d0: Vec2, d1: Vec2, f: &mut F) {
+        let cross = d0.x * d1.y - d0.y * d1.x;
+        let dot = d0.x * d1.x + d0.y * d1.y;
+        if cross == 0. {
+            //  Straight on needs no join. Turning back is joined with a round cap, or else not at all.
+            if dot < 0. && self.style.join == LineJoin::Round { self.cap(p, d0, f); }
+            return;
+        }
+        //  Offsets of the line segments on the outer side
+        let hw = if cross > 0. { -self.style.half_width } else { self.style.half_width };
+        let (n0, n1) = (perp(d0) * hw, perp(d1) * hw);
+        match self.style.join {
+            LineJoin::Miter if libm::sqrt(2. / (1. + dot)) <= self.style.miter_limit => {
+                //  Ratio of the miter length to the stroke width is 1 / sin(angle / 2), within the miter limit
+                let tip = p + (n0 + n1) / (1. + dot);
+                convex(&[p, p + n0, tip, p + n1], f);
+            }
+            LineJoin::Round => {
+                //  Arc on the shorter side between the offsets, which is the outer side
+                let angle = libm::atan2(n0.x * n1.y - n0.y * n1.x, n0.x * n1.x + n0.y * n1.y);
+                if angle > 0. { self.sector(p, n0, angle, f); }
+                else { self.sector(p, n1, -angle, f); }
+            }
+            _ => convex(&[p, p + n0, p + n1], f),
+        }
+    }
+
+    /// Cap the end of a subpath at the point, extending outwards in the direction
+    fn cap<F: FnMut(Point, Point)>(&self, p: Point, dir: Vec2, f: &mut F) {
+        let n = perp(dir) * self.style.half_width;
+        match self.style.cap {
+            LineCap::Butt => {}
+            LineCap::Square => {
+                let e = dir * self.style.half_width;
+                convex(&[p + n, p + n + e, p - n + e, p - n], f);
+            }
+            LineCap::Round => self.sector(p, -n, core::f64::consts::PI, f),
+        }
+    }
+
+    /// Add the circular sector centred at the point, from the radius vector `v` turning by `angle` radians (positive, at most pi)
+    fn sector<F: FnMut(Point, Point)>(&self, p: Point, v: Vec2, angle: f64, f: &mut F) {
+        let radius = v.hypot();
+        if radius < MIN_LENGTH { return; }
+        //  Each line segment of the arc deviates from the arc by at most the tolerance
+        let step =
+            if self.style.tolerance < radius { 2. * libm::acos(1. - self.style.tolerance / radius) }
+            else { angle };
+        let n = libm::ceil(angle / step).max(1.).min(MAX_ARC_SEGMENTS);
+        let (sin, cos) = (libm::sin(angle / n), libm::cos(angle / n));
+        let mut last = p + v;
+        f(p, last);
+        let mut r = v;
+        for _ in 1..n as usize {
+            r = Vec2::new(r.x * cos - r.y * sin, r.x * sin + r.y * cos);
+            f(last, p + r);
+            last = p + r;
+        }
+        //  End exactly at the end of the arc, without the rounding errors of the rotations
+        let end = p + Vec2::new(
+            v.x * libm::cos(angle) - v.y * libm::sin(angle),
+            v.x * libm::sin(angle) + v.y * libm::cos(angle),
+        );
+        f(last, end);
+        f(end, p);
+    }
+}
+
+/// Return the vector rotated by 90 degrees
+fn perp(v: Vec2) -> Vec2 {
+    Vec2::new(-v.y, v.x)
+}
+
+/// Add the closed convex polygon, oriented with positive area. Polygons with no area are skipped.
+fn convex<F: FnMut(Point, Point)>(points: &[Point], f: &mut F) {
+    let len = points.len();
+    let area: f64 = (0..len)
+        .map(|i| {
+            let (p0, p1) = (points[i], points[(i + 1) % len]);
+            p0.x * p1.y - p1.x * p0.y
+        })
+        .sum();
+    for i in 0..len {
+        let (p0, p1) = (points[i], points[(i + 1) % len]);
+        if area > 0. { f(p0, p1); }
+        else if area < 0. { f(p1, p0); }
+    }
+}
@@ -1,5 +1,10 @@
-use crate::{ text };
-use piet::TextLayout;
+use crate::{ batch, clip, path, raster, text, AlphaFallback, ClipMask, EmbedRenderContext, ShadowBuffer };
+use crate::text::{ BitmapFont, EmbedText };
+use embedded_graphics::{ drawable::Pixel, pixelcolor::Rgb565, unsignedcoord::UnsignedCoord, Drawing };
+use piet::kurbo::{ Affine, BezPath, Circle, Line, Point, Rect, Shape };
+use piet::{ Color, FixedLinearGradient, FixedRadialGradient, FontBuilder, GradientStops, ImageFormat, InterpolationMode, LineCap, LineJoin, LineMetric, RenderContext, StrokeStyle, Text, TextAlignment, TextLayout, TextLayoutBuilder };
+use heapless::consts::U128;
+use std::vec::Vec;
 
 // - x: calculated value
 // - target: f64
@@ -254,7 +259,6 @@ fn test_hit_test_text_position_complex_1() {
 }
 
 #[test]
-#[cfg(target_os = "linux")]
 fn test_hit_test_point_basic_0() {
     let mut text_layout = EmbedText::new();
 
@@ -266,8 +270,8 @@ fn test_hit_test_point_basic_0() {
         .new_text_layout(&font, "piet text!")
         .build()
         .unwrap();
-    println!("text pos 4: {:?}", layout.hit_test_text_position(4)); // 23.0
-    println!("text pos 5: {:?}", layout.hit_test_text_position(5)); // 27.0
+    println!("text pos 4: {:?}", layout.hit_test_text_position(4)); // 24.0
+    println!("text pos 5: {:?}", layout.hit_test_text_position(5)); // 30.0
 
     // test hit test point
     // all inside
@@ -276,67 +280,22 @@ fn test_hit_test_point_basic_0() {
     let pt = layout.hit_test_point(Point::new(23.0, 0.0));
     assert_eq!(pt.metrics.text_position, 4);
     let pt = layout.hit_test_point(Point::new(25.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 5);
+    assert_eq!(pt.metrics.text_position, 4);
     let pt = layout.hit_test_point(Point::new(26.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 5);
+    assert_eq!(pt.metrics.text_position, 4);
     let pt = layout.hit_test_point(Point::new(27.0, 0.0));
     assert_eq!(pt.metrics.text_position, 5);
     let pt = layout.hit_test_point(Point::new(28.0, 0.0));
     assert_eq!(pt.metrics.text_position, 5);
 
     // outside
-    println!("layout_width: {:?}", layout.width()); // 56.0
-
-    let pt = layout.hit_test_point(Point::new(56.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 10); // last text position
-    assert_eq!(pt.is_inside, true);
-
-    let pt = layout.hit_test_point(Point::new(57.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 10); // last text position
-    assert_eq!(pt.is_inside, false);
-
-    let pt = layout.hit_test_point(Point::new(-1.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 0); // first text position
-    assert_eq!(pt.is_inside, false);
-}
-
-#[test]
-#[cfg(target_os = "macos")]
-fn test_hit_test_point_basic_0() {
-    let mut text_layout = EmbedText::new();
-
-    let font = text_layout
-        .new_font_by_name("sans-serif", 12.0)
-        .build()
-        .unwrap();
-    let layout = text_layout
-        .new_text_layout(&font, "piet text!")
-        .build()
-        .unwrap();
-    println!("text pos 4: {:?}", layout.hit_test_text_position(4)); // 19.34765625
-    println!("text pos 5: {:?}", layout.hit_test_text_position(5)); // 22.681640625
-
-    // test hit test point
-    // all inside
-    let pt = layout.hit_test_point(Point::new(19.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 4);
-    let pt = layout.hit_test_point(Point::new(20.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 4);
-    let pt = layout.hit_test_point(Point::new(21.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 4);
-    let pt = layout.hit_test_point(Point::new(22.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 5);
-    let pt = layout.hit_test_point(Point::new(23.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 5);
-
-    // outside
-    println!("layout_width: {:?}", layout.width()); //45.357421875
+    println!("layout_width: {:?}", layout.width()); // 60.0
 
-    let pt = layout.hit_test_point(Point::new(45.0, 0.0));
+    let pt = layout.hit_test_point(Point::new(60.0, 0.0));
     assert_eq!(pt.metrics.text_position, 10); // last text position
     assert_eq!(pt.is_inside, true);
 
-    let pt = layout.hit_test_point(Point::new(46.0, 0.0));
+    let pt = layout.hit_test_point(Point::new(61.0, 0.0));
     assert_eq!(pt.metrics.text_position, 10); // last text position
     assert_eq!(pt.is_inside, false);
 
@@ -346,7 +305,6 @@ fn test_hit_test_point_basic_0() {
 }
 
 #[test]
-#[cfg(target_os = "linux")]
 // for testing that 'middle' assignment in binary search is correct
 fn test_hit_test_point_basic_1() {
     let mut text_layout = EmbedText::new();
@@ -357,14 +315,14 @@ fn test_hit_test_point_basic_1() {
         .build()
         .unwrap();
     let layout = text_layout.new_text_layout(&font, "t").build().unwrap();
-    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 5.0
+    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 6.0
 
     // two graphemes (to check that middle moves)
     let pt = layout.hit_test_point(Point::new(1.0, 0.0));
     assert_eq!(pt.metrics.text_position, 0);
 
     let layout = text_layout.new_text_layout(&font, "te").build().unwrap();
-    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 5.0
+    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 6.0
     println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 12.0
 
     let pt = layout.hit_test_point(Point::new(1.0, 0.0));
@@ -378,39 +336,6 @@ fn test_hit_test_point_basic_1() {
 }
 
 #[test]
-#[cfg(target_os = "macos")]
-// for testing that 'middle' assignment in binary search is correct
-fn test_hit_test_point_basic_1() {
-    let mut text_layout = EmbedText::new();
-
-    // base condition, one grapheme
-    let font = text_layout
-        .new_font_by_name("sans-serif", 12.0)
-        .build()
-        .unwrap();
-    let layout = text_layout.new_text_layout(&font, "t").build().unwrap();
-    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 5.0
-
-    // two graphemes (to check that middle moves)
-    let pt = layout.hit_test_point(Point::new(1.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 0);
-
-    let layout = text_layout.new_text_layout(&font, "te").build().unwrap();
-    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 5.0
-    println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 12.0
-
-    let pt = layout.hit_test_point(Point::new(1.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 0);
-    let pt = layout.hit_test_point(Point::new(4.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 1);
-    let pt = layout.hit_test_point(Point::new(6.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 1);
-    let pt = layout.hit_test_point(Point::new(11.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 2);
-}
-
-#[test]
-#[cfg(target_os = "linux")]
 fn test_hit_test_point_complex_0() {
     // Notes on this input:
     // 6 code points
@@ -425,10 +350,10 @@ fn test_hit_test_point_complex_0() {
         .build()
         .unwrap();
     let layout = text_layout.new_text_layout(&font, input).build().unwrap();
-    //println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 6.99999999
+    //println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 6.0
     //println!("text pos 9: {:?}", layout.hit_test_text_position(9)); // 24.0
-    //println!("text pos 10: {:?}", layout.hit_test_text_position(10)); // 32.0
-    //println!("text pos 14: {:?}", layout.hit_test_text_position(14)); // 39.0, line width
+    //println!("text pos 10: {:?}", layout.hit_test_text_position(10)); // 30.0
+    //println!("text pos 14: {:?}", layout.hit_test_text_position(14)); // 36.0, line width
 
     let pt = layout.hit_test_point(Point::new(2.0, 0.0));
     assert_eq!(pt.metrics.text_position, 0);
@@ -459,61 +384,9 @@ fn test_hit_test_point_complex_0() {
 }
 
 #[test]
-#[cfg(target_os = "macos")]
-fn test_hit_test_point_complex_0() {
-    // Notes on this input:
-    // 6 code points
-    // 7 utf-16 code units (1/1/1/1/1/2)
-    // 14 utf-8 code units (2/1/3/3/1/4)
-    // 4 graphemes
-    let input = "é\u{0023}\u{FE0F}\u{20E3}1\u{1D407}"; // #️⃣,, 𝐇
-
-    let mut text_layout = EmbedText::new();
-    let font = text_layout
-        .new_font_by_name("sans-serif", 12.0)
-        .build()
-        .unwrap();
-    let layout = text_layout.new_text_layout(&font, input).build().unwrap();
-    println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 6.673828125
-    println!("text pos 9: {:?}", layout.hit_test_text_position(9)); // 28.55859375
-    println!("text pos 10: {:?}", layout.hit_test_text_position(10)); // 35.232421875
-    println!("text pos 14: {:?}", layout.hit_test_text_position(14)); // 42.8378905, line width
-
-    let pt = layout.hit_test_point(Point::new(2.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 0);
-    let pt = layout.hit_test_point(Point::new(4.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 2);
-    let pt = layout.hit_test_point(Point::new(7.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 2);
-    let pt = layout.hit_test_point(Point::new(10.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 2);
-    let pt = layout.hit_test_point(Point::new(14.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 2);
-    let pt = layout.hit_test_point(Point::new(18.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 9);
-    let pt = layout.hit_test_point(Point::new(23.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 9);
-    let pt = layout.hit_test_point(Point::new(26.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 9);
-    let pt = layout.hit_test_point(Point::new(29.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 9);
-    let pt = layout.hit_test_point(Point::new(32.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 10);
-    let pt = layout.hit_test_point(Point::new(35.5, 0.0));
-    assert_eq!(pt.metrics.text_position, 10);
-    let pt = layout.hit_test_point(Point::new(38.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 10);
-    let pt = layout.hit_test_point(Point::new(40.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 14);
-    let pt = layout.hit_test_point(Point::new(43.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 14);
-}
-
-#[test]
-#[cfg(target_os = "linux")]
 fn test_hit_test_point_complex_1() {
     // this input caused an infinite loop in the binary search when test position
-    // > 21.0 && < 28.0
+    // > 18.0 && < 24.0
     //
     // This corresponds to the char 'y' in the input.
     let input = "tßßypi";
@@ -525,44 +398,1249 @@ fn test_hit_test_point_complex_1() {
         .unwrap();
     let layout = text_layout.new_text_layout(&font, input).build().unwrap();
     println!("text pos 0: {:?}", layout.hit_test_text_position(0)); // 0.0
-    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 5.0
-    println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 5.0
-    println!("text pos 3: {:?}", layout.hit_test_text_position(3)); // 13.0
-    println!("text pos 4: {:?}", layout.hit_test_text_position(4)); // 13.0
-    println!("text pos 5: {:?}", layout.hit_test_text_position(5)); // 21.0
-    println!("text pos 6: {:?}", layout.hit_test_text_position(6)); // 28.0
-    println!("text pos 7: {:?}", layout.hit_test_text_position(7)); // 36.0
-    println!("text pos 8: {:?}", layout.hit_test_text_position(8)); // 39.0, end
+    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 6.0
+    println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 6.0
+    println!("text pos 3: {:?}", layout.hit_test_text_position(3)); // 12.0
+    println!("text pos 4: {:?}", layout.hit_test_text_position(4)); // 12.0
+    println!("text pos 5: {:?}", layout.hit_test_text_position(5)); // 18.0
+    println!("text pos 6: {:?}", layout.hit_test_text_position(6)); // 24.0
+    println!("text pos 7: {:?}", layout.hit_test_text_position(7)); // 30.0
+    println!("text pos 8: {:?}", layout.hit_test_text_position(8)); // 36.0, end
 
-    let pt = layout.hit_test_point(Point::new(27.0, 0.0));
+    let pt = layout.hit_test_point(Point::new(23.0, 0.0));
     assert_eq!(pt.metrics.text_position, 6);
 }
 
+/// Rasterize the shape with the non-zero fill rule and return the spans
+fn rasterize(shape: impl piet::kurbo::Shape) -> Vec<raster::Span> {
+    rasterize_with_rule(shape, raster::FillRule::NonZero)
+}
+
+/// Rasterize the shape with anti-aliasing and return the spans
+fn rasterize_antialias(shape: impl piet::kurbo::Shape) -> Vec<raster::Span> {
+    let outline = raster::ShapeOutline::new(&shape, Affine::default(), 0.25);
+    raster::AntialiasRasterizer::new(&outline, raster::FillRule::NonZero, Rect::new(0., 0., 240., 240.)).collect()
+}
+
+/// Rasterize the shape with the fill rule and return the spans
+fn rasterize_with_rule(shape: impl piet::kurbo::Shape, rule: raster::FillRule) -> Vec<raster::Span> {
+    let outline = raster::ShapeOutline::new(&shape, Affine::default(), 0.25);
+    raster::Rasterizer::new(&outline, rule, Rect::new(0., 0., 240., 240.)).collect()
+}
+
 #[test]
-#[cfg(target_os = "macos")]
-fn test_hit_test_point_complex_1() {
-    // this input caused an infinite loop in the binary search when test position
-    // > 21.0 && < 28.0
-    //
-    // This corresponds to the char 'y' in the input.
-    let input = "tßßypi";
+fn test_raster_rect() {
+    // pixels are inside when their centres are inside
+    let spans = rasterize(Rect::new(1.0, 1.0, 5.0, 4.0));
+    assert_eq!(spans.len(), 3);
+    for (i, span) in spans.iter().enumerate() {
+        assert_eq!(*span, raster::Span { y: 1 + i as i32, x0: 1, x1: 5, alpha: 0xff });
+    }
 
-    let mut text_layout = EmbedText::new();
-    let font = text_layout
-        .new_font_by_name("sans-serif", 12.0)
-        .build()
-        .unwrap();
-    let layout = text_layout.new_text_layout(&font, input).build().unwrap();
-    println!("text pos 0: {:?}", layout.hit_test_text_position(0)); // 0.0
-    println!("text pos 1: {:?}", layout.hit_test_text_position(1)); // 5.0
-    println!("text pos 2: {:?}", layout.hit_test_text_position(2)); // 5.0
-    println!("text pos 3: {:?}", layout.hit_test_text_position(3)); // 13.0
-    println!("text pos 4: {:?}", layout.hit_test_text_position(4)); // 13.0
-    println!("text pos 5: {:?}", layout.hit_test_text_position(5)); // 21.0
-    println!("text pos 6: {:?}", layout.hit_test_text_position(6)); // 28.0
-    println!("text pos 7: {:?}", layout.hit_test_text_position(7)); // 36.0
-    println!("text pos 8: {:?}", layout.hit_test_text_position(8)); // 39.0, end
+    // clipped to the bounds
+    let spans = rasterize(Rect::new(-10.0, 230.6, 10.0, 250.0));
+    assert_eq!(spans.len(), 9);
+    assert_eq!(spans[0], raster::Span { y: 231, x0: 0, x1: 10, alpha: 0xff });
+    assert_eq!(spans[8], raster::Span { y: 239, x0: 0, x1: 10, alpha: 0xff });
 
-    let pt = layout.hit_test_point(Point::new(27.0, 0.0));
-    assert_eq!(pt.metrics.text_position, 6);
-}
\ No newline at end of file
+    // empty
+    assert!(rasterize(Rect::new(1.0, 1.0, 1.0, 5.0)).is_empty());
+}
+
+#[test]
+fn test_raster_circle() {
+    let spans = rasterize(Circle::new((50.0, 50.0), 20.0));
+    assert_eq!(spans.len(), 40);
+    for span in &spans {
+        // symmetric about the centre, and within the radius
+        assert_eq!(span.x0 + span.x1, 100);
+        let dy = span.y as f64 + 0.5 - 50.0;
+        let half_width = (20.0 * 20.0 - dy * dy).sqrt();
+        assert_close_to((span.x1 - span.x0) as f64, 2.0 * half_width, 2.0);
+    }
+}
+
+#[test]
+fn test_raster_nonzero() {
+    // the overlap of two squares with the same orientation is filled
+    let mut path = piet::kurbo::BezPath::new();
+    path.move_to((10.0, 10.0));
+    path.line_to((30.0, 10.0));
+    path.line_to((30.0, 30.0));
+    path.line_to((10.0, 30.0));
+    path.close_path();
+    path.move_to((20.0, 20.0));
+    path.line_to((40.0, 20.0));
+    path.line_to((40.0, 40.0));
+    path.line_to((20.0, 40.0));
+    path.close_path();
+    let spans = rasterize(path);
+    assert!(spans.contains(&raster::Span { y: 25, x0: 10, x1: 40, alpha: 0xff }));
+    assert_eq!(spans.len(), 30);
+}
+
+#[test]
+fn test_raster_many_edges() {
+    // more edges than fit in a band: the rows are split into smaller bands
+    let mut path = piet::kurbo::BezPath::new();
+    for i in 0..200 {
+        let angle = 2.0 * std::f64::consts::PI * (i as f64) / 200.0;
+        let p = Point::new(120.0 + 100.0 * angle.cos(), 120.0 + 100.0 * angle.sin());
+        if i == 0 { path.move_to(p); } else { path.line_to(p); }
+    }
+    path.close_path();
+    let spans = rasterize(path);
+    assert_eq!(spans.len(), 200);
+    for (i, span) in spans.iter().enumerate() {
+        assert_eq!(span.y, 20 + i as i32);
+        assert_close_to((span.x0 + span.x1) as f64, 240.0, 1.0);
+    }
+}
+
+#[test]
+fn test_raster_crowded_row() {
+    // more edges than fit in a band cross the same rows: the crossings are found in passes
+    let mut comb = piet::kurbo::BezPath::new();
+    for i in 0..50 {
+        for el in Rect::new(4.0 * i as f64, 10.0, 4.0 * i as f64 + 2.0, 12.0).to_bez_path(0.1) { comb.push(el); }
+    }
+    let spans = rasterize(comb.clone());
+    assert_eq!(spans.len(), 2 * 50);
+    for (i, span) in spans.iter().enumerate() {
+        assert_eq!(*span, raster::Span { y: 10 + (i / 50) as i32, x0: 4 * (i % 50) as i32, x1: 4 * (i % 50) as i32 + 2, alpha: 0xff });
+    }
+    assert_eq!(rasterize_antialias(comb.clone()), spans);
+
+    // the winding number is kept between passes
+    for el in Rect::new(0.0, 10.0, 240.0, 12.0).to_bez_path(0.1) { comb.push(el); }
+    assert_eq!(rasterize(comb), vec![
+        raster::Span { y: 10, x0: 0, x1: 240, alpha: 0xff },
+        raster::Span { y: 11, x0: 0, x1: 240, alpha: 0xff },
+    ]);
+}
+
+#[test]
+fn test_raster_huge_rect() {
+    // edges far beyond the screen are clamped without overflowing
+    let spans = rasterize(Rect::new(0.0, -1e7, 10.0, 1e7));
+    assert_eq!(spans.len(), 240);
+    for (y, span) in spans.iter().enumerate() {
+        assert_eq!(*span, raster::Span { y: y as i32, x0: 0, x1: 10, alpha: 0xff });
+    }
+    assert_eq!(rasterize_antialias(Rect::new(0.0, -1e7, 10.0, 1e7)), spans);
+}
+
+#[test]
+fn test_raster_even_odd() {
+    // a ring made of two circles with the same orientation
+    let mut ring = piet::kurbo::BezPath::new();
+    for el in Circle::new((50.0, 50.0), 20.0).to_bez_path(0.1) { ring.push(el); }
+    for el in Circle::new((50.0, 50.0), 10.0).to_bez_path(0.1) { ring.push(el); }
+
+    // non-zero fills the hole
+    let spans = rasterize_with_rule(ring.clone(), raster::FillRule::NonZero);
+    assert!(spans.iter().filter(|s| s.y == 49).eq([raster::Span { y: 49, x0: 30, x1: 70, alpha: 0xff }].iter()));
+
+    // even-odd leaves the hole
+    let spans = rasterize_with_rule(ring, raster::FillRule::EvenOdd);
+    assert!(spans.iter().filter(|s| s.y == 49).eq([
+        raster::Span { y: 49, x0: 30, x1: 40, alpha: 0xff },
+        raster::Span { y: 49, x0: 60, x1: 70, alpha: 0xff },
+    ].iter()));
+}
+
+#[test]
+fn test_flatten_curves() {
+    // the points are on the curve, and the chords are within the tolerance
+    let mut bez = piet::kurbo::BezPath::new();
+    bez.move_to((50.0, 10.0));
+    bez.quad_to((60.0, 50.0), (100.0, 90.0));
+    bez.curve_to((10.0, 80.0), (100.0, 80.0), (100.0, 60.0));
+    let quad = piet::kurbo::QuadBez::new((50.0, 10.0), (60.0, 50.0), (100.0, 90.0));
+    let cubic = piet::kurbo::CubicBez::new((100.0, 90.0), (10.0, 80.0), (100.0, 80.0), (100.0, 60.0));
+    let mut points = Vec::new();
+    path::flatten(bez, 0.25, |el| match el {
+        piet::kurbo::PathEl::MoveTo(p) | piet::kurbo::PathEl::LineTo(p) => points.push(p),
+        _ => panic!("curve not flattened"),
+    });
+    assert!(points.len() > 10);
+    assert_eq!(points[0], Point::new(50.0, 10.0));
+    assert_eq!(*points.last().unwrap(), Point::new(100.0, 60.0));
+    for pair in points.windows(2) {
+        let mid = pair[0].midpoint(pair[1]);
+        let near_quad = (0..=1000).any(|i| (piet::kurbo::ParamCurve::eval(&quad, i as f64 / 1000.0) - mid).hypot() < 0.3);
+        let near_cubic = (0..=1000).any(|i| (piet::kurbo::ParamCurve::eval(&cubic, i as f64 / 1000.0) - mid).hypot() < 0.3);
+        assert!(near_quad || near_cubic);
+    }
+
+    // a curve after a closed subpath starts from the start of the subpath
+    let mut bez = piet::kurbo::BezPath::new();
+    bez.move_to((0.0, 0.0));
+    bez.line_to((10.0, 0.0));
+    bez.close_path();
+    bez.quad_to((10.0, 10.0), (20.0, 0.0));
+    let quad = piet::kurbo::QuadBez::new((0.0, 0.0), (10.0, 10.0), (20.0, 0.0));
+    let mut points = Vec::new();
+    path::flatten(bez, 0.25, |el| if let piet::kurbo::PathEl::LineTo(p) = el { points.push(p) });
+    for &p in &points[1..] {
+        assert!((0..=1000).any(|i| (piet::kurbo::ParamCurve::eval(&quad, i as f64 / 1000.0) - p).hypot() < 0.01));
+    }
+}
+
+/// Draw target that records the pixels drawn
+struct TestDisplay {
+    pixels: Vec<(u32, u32, Rgb565)>,
+}
+
+impl Drawing<Rgb565> for TestDisplay {
+    fn draw<T>(&mut self, item: T)
+    where T: IntoIterator<Item = Pixel<Rgb565>> {
+        for Pixel(coord, color) in item {
+            self.pixels.push((coord.0, coord.1, color));
+        }
+    }
+}
+
+/// Render with the context and return the pixels drawn
+fn render(f: impl FnOnce(&mut EmbedRenderContext<TestDisplay>)) -> Vec<(u32, u32, Rgb565)> {
+    let mut display = TestDisplay { pixels: Vec::new() };
+    f(&mut EmbedRenderContext::new(&mut display));
+    display.pixels
+}
+
+#[test]
+fn test_context_transform() {
+    // scale then translate: the 2x2 square at (1,1) covers 4x4 pixels at (12,22)
+    let pixels = render(|rc| {
+        let brush = rc.solid_brush(Color::WHITE);
+        rc.transform(Affine::translate((10.0, 20.0)));
+        rc.transform(Affine::scale(2.0));
+        rc.fill(Rect::new(1.0, 1.0, 3.0, 3.0), &brush);
+    });
+    assert_eq!(pixels.len(), 16);
+    assert!(pixels.iter().all(|&(x, y, _)| x >= 12 && x < 16 && y >= 22 && y < 26));
+
+    // rotating a quarter turn moves the square to the left of the origin
+    let pixels = render(|rc| {
+        let brush = rc.solid_brush(Color::WHITE);
+        rc.transform(Affine::translate((100.0, 100.0)) * Affine::rotate(std::f64::consts::FRAC_PI_2));
+        rc.fill(Rect::new(0.0, 0.0, 10.0, 5.0), &brush);
+    });
+    assert_eq!(pixels.len(), 50);
+    assert!(pixels.iter().all(|&(x, y, _)| x >= 95 && x < 100 && y >= 100 && y < 110));
+}
+
+#[test]
+fn test_context_save_restore() {
+    // the transform is restored
+    let pixels = render(|rc| {
+        let brush = rc.solid_brush(Color::WHITE);
+        rc.save().unwrap();
+        rc.transform(Affine::scale(2.0));
+        rc.restore().unwrap();
+        rc.fill(Rect::new(1.0, 1.0, 3.0, 3.0), &brush);
+    });
+    assert_eq!(pixels.len(), 4);
+
+    // too many saves, or restore without save, fail
+    render(|rc| {
+        assert!(rc.restore().is_err());
+        assert!((0..10).all(|_| rc.save().is_ok()));
+        assert!(rc.save().is_err());
+    });
+}
+
+#[test]
+fn test_context_clip_rect() {
+    // the fill is clipped to the intersection of the clip rectangles, until restored
+    let pixels = render(|rc| {
+        let brush = rc.solid_brush(Color::WHITE);
+        rc.save().unwrap();
+        rc.clip(Rect::new(10.0, 10.0, 30.0, 30.0));
+        rc.save().unwrap();
+        rc.clip(Rect::new(20.0, 0.0, 100.0, 15.0));
+        rc.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &brush);
+        rc.restore().unwrap();
+        rc.stroke(piet::kurbo::Line::new((0.0, 25.5), (100.0, 25.5)), &brush, 1.0);
+        rc.restore().unwrap();
+        rc.fill(Rect::new(200.0, 200.0, 201.0, 201.0), &brush);
+    });
+    assert_eq!(pixels.iter().filter(|&&(_, y, _)| y < 20).count(), 10 * 5);
+    assert!(pixels.iter().filter(|&&(_, y, _)| y < 20).all(|&(x, y, _)| x >= 20 && x < 30 && y >= 10 && y < 15));
+    assert!(pixels.iter().filter(|&&(_, y, _)| y >= 20 && y < 100).all(|&(x, _, _)| x >= 10 && x < 30));
+    assert!(pixels.contains(&(200, 200, Rgb565::from((0xff, 0xff, 0xff)))));
+}
+
+#[test]
+fn test_context_clip_mask() {
+    let mut buffer = vec![0xff; 240 * 240];
+    let mut display = TestDisplay { pixels: Vec::new() };
+    {
+        let mut rc = EmbedRenderContext::new(&mut display).with_clip_mask(ClipMask::new(&mut buffer, 240, 240));
+        let brush = rc.solid_brush(Color::WHITE);
+        rc.save().unwrap();
+        rc.clip(Circle::new((50.0, 50.0), 20.0));
+        rc.fill(Rect::new(0.0, 0.0, 240.0, 240.0), &brush);
+        rc.restore().unwrap();
+        rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &brush);
+    }
+    // the fill is clipped to the circle, then the clip is undone
+    let circle = rasterize(Circle::new((50.0, 50.0), 20.0));
+    let circle_area: i32 = circle.iter().map(|span| span.x1 - span.x0).sum();
+    assert_eq!(display.pixels.len() as i32, circle_area + 100);
+    for span in &circle {
+        assert!(display.pixels.contains(&(span.x0 as u32, span.y as u32, Rgb565::from((0xff, 0xff, 0xff)))));
+    }
+
+    // restore only updates the pixels inside the bounds of the clip shapes
+    let mut buffer = [0; 16];
+    let mut mask = ClipMask::new(&mut buffer, 4, 4);
+    mask.clip([raster::Span { y: 1, x0: 0, x1: 4, alpha: 0xff }].iter().cloned(), 0);
+    mask.restore(0, clip::ClipRect { x0: 0, y0: 0, x1: 2, y1: 4 });
+    assert!(!mask.is_visible(1, 1, 1) && mask.is_visible(2, 1, 1));
+}
+
+#[test]
+fn test_context_independent() {
+    // two contexts keep their own state
+    let mut display0 = TestDisplay { pixels: Vec::new() };
+    let mut display1 = TestDisplay { pixels: Vec::new() };
+    {
+        let mut rc0 = EmbedRenderContext::new(&mut display0);
+        let mut rc1 = EmbedRenderContext::new(&mut display1);
+        let brush = rc0.solid_brush(Color::WHITE);
+        rc0.transform(Affine::translate((100.0, 0.0)));
+        rc1.clip(Rect::new(0.0, 0.0, 1.0, 1.0));
+        rc0.fill(Rect::new(0.0, 0.0, 2.0, 2.0), &brush);
+        rc1.fill(Rect::new(0.0, 0.0, 2.0, 2.0), &brush);
+    }
+    assert_eq!(display0.pixels.len(), 4);
+    assert!(display0.pixels.iter().all(|&(x, _, _)| x >= 100));
+    assert_eq!(display1.pixels.len(), 1);
+}
+
+#[test]
+fn test_context_size() {
+    // pixels beyond 240 are drawn on a larger draw target
+    let pixels = render(|rc| rc.fill(Rect::new(300.0, 250.0, 302.0, 252.0), &Color::WHITE));
+    assert!(pixels.is_empty());
+    let mut display = TestDisplay { pixels: Vec::new() };
+    {
+        let mut rc = EmbedRenderContext::new(&mut display).with_size(320, 480);
+        rc.fill(Rect::new(300.0, 250.0, 302.0, 252.0), &Color::WHITE);
+        rc.clear(Color::BLACK);
+    }
+    assert_eq!(display.pixels[..4], [
+        (300, 250, Rgb565::from((0xff, 0xff, 0xff))), (301, 250, Rgb565::from((0xff, 0xff, 0xff))),
+        (300, 251, Rgb565::from((0xff, 0xff, 0xff))), (301, 251, Rgb565::from((0xff, 0xff, 0xff))),
+    ]);
+    assert_eq!(display.pixels.len(), 4 + 320 * 480);
+}
+
+#[test]
+fn test_context_clear() {
+    // every clear fills the clip region
+    let pixels = render(|rc| {
+        rc.clear(Color::BLACK);
+        rc.clip(Rect::new(10.0, 20.0, 30.0, 25.0));
+        rc.clear(Color::WHITE);
+        rc.clear(Color::WHITE);
+    });
+    let white = Rgb565::from((0xff, 0xff, 0xff));
+    assert_eq!(pixels.iter().filter(|&&(_, _, c)| c != white).count(), 240 * 240);
+    assert_eq!(pixels.iter().filter(|&&(_, _, c)| c == white).count(), 2 * 20 * 5);
+    assert!(pixels.iter().filter(|&&(_, _, c)| c == white).all(|&(x, y, _)| x >= 10 && x < 30 && y >= 20 && y < 25));
+}
+
+/// Return the red component of the pixel at (x,y)
+fn red_at(pixels: &[(u32, u32, Rgb565)], x: u32, y: u32) -> u16 {
+    let &(_, _, color) = pixels.iter().find(|&&(px, py, _)| px == x && py == y).unwrap();
+    color.0 >> 11
+}
+
+#[test]
+fn test_context_linear_gradient() {
+    // white to black from x = 10 to 70, moved by the transform
+    let pixels = render(|rc| {
+        let brush = rc.gradient(FixedLinearGradient {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(60.0, 0.0),
+            stops: (Color::WHITE, Color::BLACK).to_vec(),
+        }).unwrap();
+        rc.transform(Affine::translate((10.0, 0.0)));
+        rc.fill(Rect::new(-10.0, 0.0, 70.0, 10.0), &brush);
+    });
+    assert_eq!(red_at(&pixels, 0, 5), 31);
+    assert_eq!(red_at(&pixels, 10, 5), 31);
+    assert_eq!(red_at(&pixels, 40, 5), 15);
+    assert_eq!(red_at(&pixels, 79, 5), 0);
+    for x in 1..80 {
+        assert!(red_at(&pixels, x, 5) <= red_at(&pixels, x - 1, 5));
+    }
+}
+
+#[test]
+fn test_context_radial_gradient() {
+    // white at the centre to black at the radius
+    let pixels = render(|rc| {
+        let brush = rc.gradient(FixedRadialGradient {
+            center: Point::new(50.0, 50.0),
+            origin_offset: piet::kurbo::Vec2::ZERO,
+            radius: 30.0,
+            stops: (Color::WHITE, Color::BLACK).to_vec(),
+        }).unwrap();
+        rc.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &brush);
+    });
+    assert_eq!(red_at(&pixels, 50, 50), 31);
+    assert_eq!(red_at(&pixels, 50, 65), 15);
+    assert_eq!(red_at(&pixels, 65, 50), 15);
+    assert_eq!(red_at(&pixels, 90, 50), 0);
+
+    // the focal point is offset towards the left
+    let pixels = render(|rc| {
+        let brush = rc.gradient(FixedRadialGradient {
+            center: Point::new(50.0, 50.0),
+            origin_offset: piet::kurbo::Vec2::new(-20.0, 0.0),
+            radius: 30.0,
+            stops: (Color::WHITE, Color::BLACK).to_vec(),
+        }).unwrap();
+        rc.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &brush);
+    });
+    assert_eq!(red_at(&pixels, 30, 50), 31);
+    assert!(red_at(&pixels, 25, 50) < red_at(&pixels, 35, 50));
+    assert_eq!(red_at(&pixels, 19, 50), 0);
+    assert_eq!(red_at(&pixels, 79, 50), 0);
+
+    // the focal point is outside the circle: only the cone of circles is drawn
+    let pixels = render(|rc| {
+        let brush = rc.gradient(FixedRadialGradient {
+            center: Point::new(60.0, 50.0),
+            origin_offset: piet::kurbo::Vec2::new(-40.0, 0.0),
+            radius: 10.0,
+            stops: (Color::WHITE, Color::BLACK).to_vec(),
+        }).unwrap();
+        rc.fill(Rect::new(0.0, 0.0, 100.0, 100.0), &brush);
+    });
+    assert!(red_at(&pixels, 30, 50) > 0 && red_at(&pixels, 30, 50) < 31);
+    assert_eq!(red_at(&pixels, 60, 50), 0);
+    assert!(!has_pixel(&pixels, 20, 80));  //  Beside the focal point
+    assert!(!has_pixel(&pixels, 60, 80));  //  Beside the circle
+    assert!(!has_pixel(&pixels, 5, 50));   //  Behind the focal point
+}
+
+#[test]
+fn test_context_blend_shadow() {
+    // translucent white over red is blended with the shadow buffer
+    let mut buffer = vec![0; 240 * 240];
+    let mut display = TestDisplay { pixels: Vec::new() };
+    {
+        let mut rc = EmbedRenderContext::new(&mut display).with_shadow_buffer(ShadowBuffer::new(&mut buffer, 240, 240));
+        rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(0xff, 0, 0));
+        rc.fill(Rect::new(5.0, 0.0, 10.0, 10.0), &Color::rgba8(0xff, 0xff, 0xff, 0x80));
+        rc.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgba8(0, 0, 0xff, 0));
+    }
+    assert_eq!(display.pixels.len(), 150);
+    let (_, _, color) = display.pixels.last().unwrap();
+    assert_eq!(color.0 >> 11, 31);
+    assert_eq!(color.0 >> 5 & 0x3f, 32);
+    assert_eq!(color.0 & 0x1f, 16);
+    assert_eq!(buffer[5], color.0);
+    assert_eq!(buffer[4], Rgb565::from((0xff, 0, 0)).0);
+}
+
+#[test]
+fn test_context_blend_fallback() {
+    // without a shadow buffer, a quarter of the pixels are dithered
+    let pixels = render(|rc| {
+        rc.fill(Rect::new(0.0, 0.0, 16.0, 16.0), &Color::rgba8(0xff, 0xff, 0xff, 0x40));
+    });
+    assert_eq!(pixels.len(), 16 * 16 / 4);
+
+    // or all pixels are opaque
+    let mut display = TestDisplay { pixels: Vec::new() };
+    EmbedRenderContext::new(&mut display)
+        .with_alpha_fallback(AlphaFallback::Opaque)
+        .fill(Rect::new(0.0, 0.0, 16.0, 16.0), &Color::rgba8(0xff, 0xff, 0xff, 0x40));
+    assert_eq!(display.pixels.len(), 16 * 16);
+}
+
+#[test]
+fn test_raster_antialias() {
+    // pixel-aligned edges are fully covered
+    let spans = rasterize_antialias(Rect::new(1.0, 1.0, 5.0, 4.0));
+    assert_eq!(spans, rasterize(Rect::new(1.0, 1.0, 5.0, 4.0)));
+
+    // edges through the middle of the pixels are half covered
+    let spans = rasterize_antialias(Rect::new(1.5, 1.5, 5.0, 4.0));
+    assert_eq!(spans[0], raster::Span { y: 1, x0: 1, x1: 2, alpha: 64 });
+    assert_eq!(spans[1], raster::Span { y: 1, x0: 2, x1: 5, alpha: 128 });
+    assert_eq!(spans[2], raster::Span { y: 2, x0: 1, x1: 2, alpha: 128 });
+    assert_eq!(spans[3], raster::Span { y: 2, x0: 2, x1: 5, alpha: 0xff });
+    assert_eq!(spans.len(), 6);
+
+    // the total coverage of a circle is within 1% of the number of pixels in the aliased circle
+    let spans = rasterize_antialias(Circle::new((50.0, 50.0), 20.0));
+    let coverage: f64 = spans.iter().map(|span| ((span.x1 - span.x0) * span.alpha as i32) as f64 / 255.0).sum();
+    let pixels: i32 = rasterize(Circle::new((50.0, 50.0), 20.0)).iter().map(|span| span.x1 - span.x0).sum();
+    assert_close_to(coverage, pixels as f64, pixels as f64 / 100.0);
+}
+
+#[test]
+fn test_context_antialias() {
+    // the edge pixels are blended with the background
+    let mut buffer = vec![0; 240 * 240];
+    let mut display = TestDisplay { pixels: Vec::new() };
+    EmbedRenderContext::new(&mut display)
+        .with_shadow_buffer(ShadowBuffer::new(&mut buffer, 240, 240))
+        .with_antialias(true)
+        .fill(Rect::new(0.0, 0.0, 10.5, 10.0), &Color::WHITE);
+    assert_eq!(display.pixels.len(), 110);
+    assert_eq!(red_at(&display.pixels, 9, 5), 31);
+    assert_eq!(red_at(&display.pixels, 10, 5), 16);
+
+    // aliased by default
+    let pixels = render(|rc| rc.fill(Rect::new(0.0, 0.0, 10.5, 10.0), &Color::WHITE));
+    assert_eq!(pixels.len(), 100);
+}
+
+/// Return true if the pixel at (x,y) was drawn
+fn has_pixel(pixels: &[(u32, u32, Rgb565)], x: u32, y: u32) -> bool {
+    pixels.iter().any(|&(px, py, _)| px == x && py == y)
+}
+
+/// Render the shape stroked with the width, cap and join
+fn render_stroke(shape: impl Shape, width: f64, cap: LineCap, join: LineJoin) -> Vec<(u32, u32, Rgb565)> {
+    let mut style = StrokeStyle::new();
+    style.set_line_cap(cap);
+    style.set_line_join(join);
+    render(|rc| rc.stroke_styled(shape, &Color::WHITE, width, &style))
+}
+
+#[test]
+fn test_stroke_caps() {
+    let line = Line::new((20.0, 20.0), (40.0, 20.0));
+    let butt = render_stroke(line, 6.0, LineCap::Butt, LineJoin::Miter);
+    assert_eq!(butt.len(), 20 * 6);
+    assert!(!has_pixel(&butt, 19, 20) && has_pixel(&butt, 20, 20));
+    assert!(has_pixel(&butt, 39, 20) && !has_pixel(&butt, 40, 20));
+
+    let square = render_stroke(line, 6.0, LineCap::Square, LineJoin::Miter);
+    assert_eq!(square.len(), 26 * 6);
+    assert!(has_pixel(&square, 17, 17) && has_pixel(&square, 42, 22));
+
+    let round = render_stroke(line, 6.0, LineCap::Round, LineJoin::Miter);
+    assert!(has_pixel(&round, 17, 20) && has_pixel(&round, 42, 20));
+    assert!(!has_pixel(&round, 17, 17) && !has_pixel(&round, 42, 22));
+}
+
+#[test]
+fn test_stroke_joins() {
+    let mut path = BezPath::new();
+    path.move_to((20.0, 20.0));
+    path.line_to((40.0, 20.0));
+    path.line_to((40.0, 40.0));
+
+    //  The outer corner of the join is at (45,15)
+    let miter = render_stroke(path.clone(), 10.0, LineCap::Butt, LineJoin::Miter);
+    assert!(has_pixel(&miter, 44, 15) && has_pixel(&miter, 42, 16));
+    let round = render_stroke(path.clone(), 10.0, LineCap::Butt, LineJoin::Round);
+    assert!(!has_pixel(&round, 44, 15) && has_pixel(&round, 42, 16));
+    let bevel = render_stroke(path.clone(), 10.0, LineCap::Butt, LineJoin::Bevel);
+    assert!(!has_pixel(&bevel, 44, 15) && !has_pixel(&bevel, 42, 16));
+
+    //  The miter of a right angle is longer than the miter limit
+    let mut style = StrokeStyle::new();
+    style.miter_limit = Some(1.2);
+    let limited = render(|rc| rc.stroke_styled(path.clone(), &Color::WHITE, 10.0, &style));
+    assert_eq!(limited, bevel);
+
+    //  Closed paths are joined at the start point
+    let rect = render_stroke(Rect::new(20.0, 20.0, 40.0, 40.0), 10.0, LineCap::Round, LineJoin::Miter);
+    assert!(has_pixel(&rect, 15, 15) && has_pixel(&rect, 44, 44));
+}
+
+#[test]
+fn test_stroke_overlap() {
+    //  Each pixel of a translucent stroke is blended once, where the segments and joins overlap
+    let mut path = BezPath::new();
+    path.move_to((20.0, 20.0));
+    path.line_to((60.0, 20.0));
+    path.line_to((25.0, 30.0));
+    path.line_to((50.0, 50.0));
+    let mut style = StrokeStyle::new();
+    style.set_line_join(LineJoin::Round);
+    let mut buffer = vec![0; 240 * 240];
+    let mut display = TestDisplay { pixels: Vec::new() };
+    EmbedRenderContext::new(&mut display)
+        .with_shadow_buffer(ShadowBuffer::new(&mut buffer, 240, 240))
+        .stroke_styled(path, &Color::rgba8(0xff, 0xff, 0xff, 0x80), 8.0, &style);
+    let color = display.pixels[0].2;
+    assert!(display.pixels.iter().all(|&(_, _, c)| c == color));
+    let mut coords: Vec<(u32, u32)> = display.pixels.iter().map(|&(x, y, _)| (x, y)).collect();
+    coords.sort();
+    coords.dedup();
+    assert_eq!(coords.len(), display.pixels.len());
+}
+
+/// Render the shape stroked with the width and dash pattern, and return the pixels in row `y`
+fn render_dash(shape: impl Shape, dashes: &[f64], offset: f64, y: u32) -> Vec<u32> {
+    let mut style = StrokeStyle::new();
+    style.set_dash(dashes.iter().cloned().collect(), offset);
+    let pixels = render(|rc| rc.stroke_styled(shape, &Color::WHITE, 2.0, &style));
+    let mut row: Vec<u32> = pixels.iter().filter(|&&(_, py, _)| py == y).map(|&(x, _, _)| x).collect();
+    row.sort();
+    row
+}
+
+/// Return the columns from the ranges
+fn columns(ranges: &[(u32, u32)]) -> Vec<u32> {
+    ranges.iter().flat_map(|&(x0, x1)| x0..x1).collect()
+}
+
+#[test]
+fn test_stroke_dash() {
+    let line = Line::new((20.0, 20.0), (60.0, 20.0));
+    assert_eq!(render_dash(line, &[10.0, 5.0], 0.0, 20), columns(&[(20, 30), (35, 45), (50, 60)]));
+    //  Offset into the pattern
+    assert_eq!(render_dash(line, &[10.0, 5.0], 5.0, 20), columns(&[(20, 25), (30, 40), (45, 55)]));
+    assert_eq!(render_dash(line, &[10.0, 5.0], -5.0, 20), columns(&[(25, 35), (40, 50), (55, 60)]));
+    //  Odd patterns are repeated
+    assert_eq!(render_dash(line, &[8.0], 0.0, 20), columns(&[(20, 28), (36, 44), (52, 60)]));
+    //  Invalid patterns are solid
+    assert_eq!(render_dash(line, &[0.0, 0.0], 0.0, 20), columns(&[(20, 60)]));
+    //  Patterns too short to be seen are solid, instead of splitting the line into countless dashes
+    assert_eq!(render_dash(line, &[1e-9, 1e-9], 0.0, 20), columns(&[(20, 60)]));
+    //  Every dash of a dotted gridline across the display is drawn
+    let gridline = Line::new((0.0, 100.0), (240.0, 100.0));
+    let dashes: Vec<(u32, u32)> = (0..60).map(|i| (4 * i, 4 * i + 2)).collect();
+    assert_eq!(render_dash(gridline, &[2.0, 2.0], 0.0, 100), columns(&dashes));
+
+    //  Dashes continue across line segments, with joins
+    let mut path = BezPath::new();
+    path.move_to((20.0, 20.0));
+    path.line_to((30.0, 20.0));
+    path.line_to((30.0, 40.0));
+    assert_eq!(render_dash(path.clone(), &[15.0, 5.0], 0.0, 19), columns(&[(20, 31)]));
+    assert_eq!(render_dash(path.clone(), &[15.0, 5.0], 0.0, 27), columns(&[]));
+    assert_eq!(render_dash(path.clone(), &[15.0, 5.0], 0.0, 32), columns(&[(29, 31)]));
+
+    //  Dashes that continue through the start of a closed path are joined
+    let rect = Rect::new(20.0, 20.0, 40.0, 40.0);
+    assert_eq!(render_dash(rect, &[60.0, 20.0], 10.0, 19), columns(&[(19, 41)]));
+    assert_eq!(render_dash(rect, &[60.0, 20.0], 0.0, 19), columns(&[(20, 41)]));
+
+    //  Dashes follow curves
+    let circle = Circle::new((100.0, 100.0), 20.0);
+    let solid = render(|rc| rc.stroke_styled(circle, &Color::WHITE, 2.0, &StrokeStyle::new())).len();
+    let mut style = StrokeStyle::new();
+    style.set_dash([4.0, 4.0].iter().cloned().collect(), 0.0);
+    let dashed = render(|rc| rc.stroke_styled(circle, &Color::WHITE, 2.0, &style)).len();
+    assert!(dashed > solid * 4 / 10 && dashed < solid * 6 / 10);
+}
+
+/// Return the sorted rows drawn in column `x`
+fn rows_at(pixels: &[(u32, u32, Rgb565)], x: u32) -> Vec<u32> {
+    let mut rows: Vec<u32> = pixels.iter().filter(|&&(px, _, _)| px == x).map(|&(_, y, _)| y).collect();
+    rows.sort();
+    rows
+}
+
+#[test]
+fn test_stroke_width() {
+    let line = Line::new((20.0, 20.0), (60.0, 20.0));
+    //  Fractional widths are not truncated
+    let pixels = render(|rc| rc.stroke(line, &Color::WHITE, 3.0));
+    assert_eq!(rows_at(&pixels, 40), vec![18, 19, 20]);
+    let pixels = render(|rc| rc.stroke(line, &Color::WHITE, 1.5));
+    assert_eq!(rows_at(&pixels, 40), vec![19, 20]);
+    //  Thin strokes are 1 pixel wide
+    let pixels = render(|rc| rc.stroke(line, &Color::WHITE, 0.5));
+    assert_eq!(rows_at(&pixels, 40), vec![19]);
+    assert_eq!(pixels.len(), 40);
+    //  Widths are scaled by the transform
+    let pixels = render(|rc| {
+        rc.transform(Affine::scale(2.0));
+        rc.stroke(Line::new((10.0, 10.0), (30.0, 10.0)), &Color::WHITE, 1.5);
+    });
+    assert_eq!(rows_at(&pixels, 40), vec![18, 19, 20]);
+
+    //  Wide strokes don't wrap around
+    let pixels = render(|rc| rc.stroke(Line::new((0.0, 120.0), (240.0, 120.0)), &Color::WHITE, 300.0));
+    assert_eq!(pixels.len(), 240 * 240);
+
+    //  Thick strokes are mitred at the corners between line segments, by default
+    let mut path = BezPath::new();
+    path.move_to((20.0, 20.0));
+    path.line_to((40.0, 20.0));
+    path.line_to((40.0, 40.0));
+    let pixels = render(|rc| rc.stroke(path, &Color::WHITE, 10.0));
+    assert!(has_pixel(&pixels, 44, 15) && has_pixel(&pixels, 40, 20));
+    assert!(!has_pixel(&pixels, 45, 15));
+}
+
+/// Return the colour of the pixel at (x,y)
+fn color_at(pixels: &[(u32, u32, Rgb565)], x: u32, y: u32) -> Rgb565 {
+    let &(_, _, color) = pixels.iter().find(|&&(px, py, _)| px == x && py == y).unwrap();
+    color
+}
+
+/// 2 x 2 image: red, green, blue, white
+static IMAGE_RGB: [u8; 12] = [
+    0xff, 0x00, 0x00,   0x00, 0xff, 0x00,
+    0x00, 0x00, 0xff,   0xff, 0xff, 0xff,
+];
+
+/// 2 x 1 image: opaque red with separate alpha, transparent
+static IMAGE_RGBA: [u8; 8] = [
+    0xff, 0x00, 0x00, 0xff,   0xff, 0xff, 0xff, 0x00,
+];
+
+/// 2 x 1 image: opaque red with premultiplied alpha, half transparent blue
+static IMAGE_PREMUL: [u8; 8] = [
+    0xff, 0x00, 0x00, 0xff,   0x00, 0x00, 0x80, 0x80,
+];
+
+#[test]
+fn test_image_draw() {
+    //  Buffer must be large enough for the image
+    let mut display = TestDisplay { pixels: Vec::new() };
+    let mut rc = EmbedRenderContext::new(&mut display);
+    assert!(rc.make_image(3, 2, &IMAGE_RGB, ImageFormat::Rgb).is_err());
+    assert!(rc.make_image(0, 2, &IMAGE_RGB, ImageFormat::Rgb).is_err());
+
+    //  Image is scaled to the rectangle
+    let pixels = render(|rc| {
+        let image = rc.make_image(2, 2, &IMAGE_RGB, ImageFormat::Rgb).unwrap();
+        rc.draw_image(&image, Rect::new(10.0, 10.0, 30.0, 30.0), InterpolationMode::NearestNeighbor);
+    });
+    assert_eq!(pixels.len(), 400);
+    assert_eq!(color_at(&pixels, 10, 10), Rgb565::from((0xff, 0x00, 0x00)));
+    assert_eq!(color_at(&pixels, 29, 10), Rgb565::from((0x00, 0xff, 0x00)));
+    assert_eq!(color_at(&pixels, 19, 29), Rgb565::from((0x00, 0x00, 0xff)));
+    assert_eq!(color_at(&pixels, 20, 20), Rgb565::from((0xff, 0xff, 0xff)));
+
+    //  Image is transformed with the rectangle
+    let pixels = render(|rc| {
+        let image = rc.make_image(2, 2, &IMAGE_RGB, ImageFormat::Rgb).unwrap();
+        rc.transform(Affine::translate((100.0, 0.0)) * Affine::scale(2.0));
+        rc.draw_image(&image, Rect::new(10.0, 10.0, 20.0, 20.0), InterpolationMode::NearestNeighbor);
+    });
+    assert_eq!(pixels.len(), 400);
+    assert_eq!(color_at(&pixels, 120, 20), Rgb565::from((0xff, 0x00, 0x00)));
+    assert_eq!(color_at(&pixels, 139, 39), Rgb565::from((0xff, 0xff, 0xff)));
+
+    //  Transparent pixels are skipped
+    let pixels = render(|rc| {
+        let image = rc.make_image(2, 1, &IMAGE_RGBA, ImageFormat::RgbaSeparate).unwrap();
+        rc.draw_image(&image, Rect::new(0.0, 0.0, 20.0, 10.0), InterpolationMode::NearestNeighbor);
+    });
+    assert_eq!(pixels.len(), 100);
+    assert_eq!(color_at(&pixels, 9, 9), Rgb565::from((0xff, 0x00, 0x00)));
+
+    //  Premultiplied colours are divided by the alpha
+    let mut buffer = vec![0; 240 * 240];
+    let mut display = TestDisplay { pixels: Vec::new() };
+    let mut rc = EmbedRenderContext::new(&mut display)
+        .with_shadow_buffer(ShadowBuffer::new(&mut buffer, 240, 240));
+    let image = rc.make_image(2, 1, &IMAGE_PREMUL, ImageFormat::RgbaPremul).unwrap();
+    rc.draw_image(&image, Rect::new(0.0, 0.0, 2.0, 1.0), InterpolationMode::NearestNeighbor);
+    assert_eq!(color_at(&display.pixels, 0, 0), Rgb565::from((0xff, 0x00, 0x00)));
+    assert_eq!(color_at(&display.pixels, 1, 0), Rgb565::from((0x00, 0x00, 0x80)));
+}
+
+/// Palette of Rgb565 colours: red, green, blue
+static PALETTE: [u16; 3] = [0xf800, 0x07e0, 0x001f];
+
+/// Draw the 1-row image at 1:1 scale and return the pixels
+fn render_image(width: usize, pixels: &'static [u8], format: ImageFormat) -> Vec<(u32, u32, Rgb565)> {
+    render(|rc| {
+        let image = rc.make_image(width, 1, pixels, format).unwrap();
+        rc.draw_image(&image, Rect::new(0.0, 0.0, width as f64, 1.0), InterpolationMode::NearestNeighbor);
+    })
+}
+
+#[test]
+fn test_image_formats() {
+    //  Rgb565 pixels are copied in either byte order
+    static RGB565_LE: [u8; 4] = [0x00, 0xf8, 0x1f, 0x00];
+    static RGB565_BE: [u8; 4] = [0xf8, 0x00, 0x00, 0x1f];
+    for &(pixels, format) in &[(&RGB565_LE, ImageFormat::Rgb565Le), (&RGB565_BE, ImageFormat::Rgb565Be)] {
+        let pixels = render_image(2, pixels, format);
+        assert_eq!(color_at(&pixels, 0, 0), Rgb565(0xf800));
+        assert_eq!(color_at(&pixels, 1, 0), Rgb565(0x001f));
+    }
+
+    static GREY: [u8; 2] = [0x00, 0xff];
+    let pixels = render_image(2, &GREY, ImageFormat::Grayscale8);
+    assert_eq!(color_at(&pixels, 0, 0), Rgb565(0x0000));
+    assert_eq!(color_at(&pixels, 1, 0), Rgb565(0xffff));
+
+    //  Alpha masks are black, and transparent pixels are skipped
+    static ALPHA: [u8; 2] = [0x00, 0xff];
+    let pixels = render_image(2, &ALPHA, ImageFormat::Alpha8);
+    assert_eq!(pixels, vec![(1, 0, Rgb565(0x0000))]);
+
+    //  Palette indexes beyond the palette are transparent
+    static INDEXED8: [u8; 4] = [2, 0, 1, 3];
+    let pixels = render_image(4, &INDEXED8, ImageFormat::Indexed8(&PALETTE));
+    assert_eq!(pixels, vec![(0, 0, Rgb565(0x001f)), (1, 0, Rgb565(0xf800)), (2, 0, Rgb565(0x07e0))]);
+    static INDEXED4: [u8; 2] = [0x21, 0x0f];
+    let pixels = render_image(3, &INDEXED4, ImageFormat::Indexed4(&PALETTE));
+    assert_eq!(pixels, vec![(0, 0, Rgb565(0x001f)), (1, 0, Rgb565(0x07e0)), (2, 0, Rgb565(0xf800))]);
+
+    //  Rows of 4-bit pixels start on a new byte
+    assert_eq!(ImageFormat::Indexed4(&PALETTE).bytes_per_row(3), 2);
+    let mut display = TestDisplay { pixels: Vec::new() };
+    let mut rc = EmbedRenderContext::new(&mut display);
+    assert!(rc.make_image(3, 2, &INDEXED4, ImageFormat::Indexed4(&PALETTE)).is_err());
+    assert!(rc.make_image(3, 1, &INDEXED4, ImageFormat::Indexed4(&PALETTE)).is_ok());
+}
+
+/// 2 x 1 image: red, blue
+static IMAGE_RED_BLUE: [u8; 6] = [0xff, 0x00, 0x00,   0x00, 0x00, 0xff];
+
+/// Draw the red and blue image scaled to 20 x 1 pixels with the interpolation mode
+fn render_scaled(interp: InterpolationMode) -> Vec<(u32, u32, Rgb565)> {
+    render(|rc| {
+        let image = rc.make_image(2, 1, &IMAGE_RED_BLUE, ImageFormat::Rgb).unwrap();
+        rc.draw_image(&image, Rect::new(0.0, 0.0, 20.0, 1.0), interp);
+    })
+}
+
+#[test]
+fn test_image_scaling() {
+    //  Nearest neighbour switches colour halfway
+    let pixels = render_scaled(InterpolationMode::NearestNeighbor);
+    assert_eq!(color_at(&pixels, 9, 0), Rgb565::from((0xff, 0x00, 0x00)));
+    assert_eq!(color_at(&pixels, 10, 0), Rgb565::from((0x00, 0x00, 0xff)));
+
+    //  Bilinear blends between the pixel centres, and pads at the edges
+    let pixels = render_scaled(InterpolationMode::Bilinear);
+    assert_eq!(color_at(&pixels, 0, 0), Rgb565::from((0xff, 0x00, 0x00)));
+    assert_eq!(color_at(&pixels, 19, 0), Rgb565::from((0x00, 0x00, 0xff)));
+    let reds: Vec<u16> = (0..20).map(|x| red_at(&pixels, x, 0)).collect();
+    assert!(reds.windows(2).all(|pair| pair[0] >= pair[1]));
+    assert!(reds[9] > 10 && reds[9] < 21 && reds[10] > 10 && reds[10] < 21);
+
+    //  Bilinear at the original size is the same as nearest neighbour
+    let draw = |interp| render(|rc| {
+        let image = rc.make_image(2, 2, &IMAGE_RGB, ImageFormat::Rgb).unwrap();
+        rc.draw_image(&image, Rect::new(10.0, 10.0, 12.0, 12.0), interp);
+    });
+    assert_eq!(draw(InterpolationMode::Bilinear), draw(InterpolationMode::NearestNeighbor));
+
+    //  Transparent pixels don't darken the blended colour
+    let pixels = render(|rc| {
+        let image = rc.make_image(2, 1, &IMAGE_RGBA, ImageFormat::RgbaSeparate).unwrap();
+        rc.draw_image(&image, Rect::new(0.0, 0.0, 20.0, 1.0), InterpolationMode::Bilinear);
+    });
+    assert!(pixels.iter().all(|&(_, _, color)| color == Rgb565::from((0xff, 0x00, 0x00))));
+}
+
+/// Render each character as a 2 x 3 block of stroked pixels
+fn render_blocks(text: &str, stroke: Rgb565, _fill: Option<Rgb565>, pixels: &mut dyn FnMut(&mut dyn Iterator<Item = Pixel<Rgb565>>)) {
+    let width = 2 * text.chars().count() as u32;
+    let mut glyphs = (0..3).flat_map(move |y| (0..width).map(move |x| Pixel(UnsignedCoord::new(x, y), stroke)));
+    pixels(&mut glyphs)
+}
+
+#[test]
+fn test_font_registry() {
+    //  Closest size is selected, preferring fonts with the name
+    let mut text = EmbedText::new();
+    let font = text.new_font_by_name("sans-serif", 12.0).build().unwrap();
+    assert_eq!(font.name(), "Font6x12");
+    let font = text.new_font_by_name("sans-serif", 7.0).build().unwrap();
+    assert_eq!(font.name(), "Font6x8");
+    let font = text.new_font_by_name("font12x16", 8.0).build().unwrap();
+    assert_eq!(font.name(), "Font12x16");
+
+    //  Width uses the metrics of the font
+    let layout = text.new_text_layout(&font, "piet").build().unwrap();
+    assert_eq!(layout.width(), 48.0);
+    let font = text.new_font_by_name("Font8x16", 16.0).build().unwrap();
+    let layout = text.new_text_layout(&font, "é!").build().unwrap();
+    assert_eq!(layout.width(), 16.0);
+
+    //  Custom fonts are registered until the registry is full
+    text.register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+    let font = text.new_font_by_name("Blocks", 20.0).build().unwrap();
+    assert_eq!(font.name(), "Blocks");
+    assert_eq!(text.new_text_layout(&font, "abc").build().unwrap().width(), 6.0);
+    while text.register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).is_ok() {}
+
+    //  Text is drawn with the layout's font
+    let pixels = render(|rc| {
+        rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+        let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "abc").build().unwrap();
+        rc.draw_text(&layout, (10.0, 23.0), &Color::WHITE);
+    });
+    assert_eq!(pixels.len(), 18);
+    assert!(has_pixel(&pixels, 10, 20) && has_pixel(&pixels, 15, 22));
+    assert!(!has_pixel(&pixels, 16, 20));
+}
+
+#[test]
+fn test_text_capacity() {
+    //  Text longer than the capacity is an error
+    let body = "Your heart rate is 72 bpm, and you have walked 4,096 steps today. Keep it up!";
+    let mut text = EmbedText::new();
+    let font = text.new_font_by_name("Font6x8", 8.0).build().unwrap();
+    assert!(text.new_text_layout(&font, &body[..64]).build().is_ok());
+    assert!(text.new_text_layout(&font, body).build().is_err());
+
+    //  Capacity is set by the type
+    let mut text = EmbedText::<U128>::with_capacity();
+    let font = text.new_font_by_name("Font6x8", 8.0).build().unwrap();
+    let layout = text.new_text_layout(&font, body).build().unwrap();
+    assert_eq!(layout.width(), 6.0 * body.len() as f64);
+
+    //  Capacity of the context, keeping the registered fonts
+    let mut display = TestDisplay { pixels: Vec::new() };
+    let mut rc = EmbedRenderContext::new(&mut display);
+    rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+    let mut rc = rc.with_text_capacity::<U128>();
+    let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+    let layout = rc.text().new_text_layout(&font, body).build().unwrap();
+    rc.draw_text(&layout, (0.0, 3.0), &Color::WHITE);
+    assert_eq!(display.pixels.len(), 6 * body.len());
+}
+
+#[test]
+fn test_text_wrap() {
+    //  Lines break at the last opportunity that fits, keeping the trailing whitespace
+    let mut text = EmbedText::new();
+    let font = text.new_font_by_name("Font6x12", 12.0).build().unwrap();
+    let layout = text.new_text_layout(&font, "hello world foo").max_width(60.0).build().unwrap();
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(layout.line_text(0), Some("hello "));
+    assert_eq!(layout.line_text(1), Some("world foo"));
+    assert_eq!(layout.line_text(2), None);
+    assert_eq!(layout.line_metric(1), Some(LineMetric {
+        start_offset: 6, end_offset: 15, trailing_whitespace: 0, baseline: 10.0, height: 12.0, y_offset: 12.0,
+    }));
+    assert_eq!(layout.width(), 54.0);
+    assert_eq!(layout.height(), 24.0);
+
+    //  Without a max width, lines break only at hard breaks
+    let layout = text.new_text_layout(&font, "hello world\nfoo").build().unwrap();
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(layout.line_metric(0).unwrap().trailing_whitespace, 1);
+    assert_eq!(layout.width(), 66.0);
+
+    //  Words that are too wide overflow the line, and empty text has one line
+    let layout = text.new_text_layout(&font, "heartbeat").max_width(30.0).build().unwrap();
+    assert_eq!(layout.line_count(), 1);
+    let layout = text.new_text_layout(&font, "").max_width(30.0).build().unwrap();
+    assert_eq!(layout.line_count(), 1);
+    assert_eq!(layout.height(), 12.0);
+
+    //  A hard break at the end of the text starts an empty line
+    let layout = text.new_text_layout(&font, "a\n").build().unwrap();
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(layout.line_text(1), Some(""));
+    assert_eq!(layout.height(), 24.0);
+    let hit = layout.hit_test_point(Point::new(10.0, 15.0));
+    assert_eq!((hit.metrics.text_position, hit.is_inside), (2, false));
+    assert_eq!(layout.hit_test_text_position(2).unwrap().point, Point::new(0.0, 12.0));
+
+    //  Hit tests select the line by the y coordinate, relative to the first baseline
+    let layout = text.new_text_layout(&font, "hello world foo").max_width(60.0).build().unwrap();
+    let hit = layout.hit_test_point(Point::new(10.0, 3.0));
+    assert_eq!((hit.metrics.text_position, hit.is_inside), (8, true));
+    let hit = layout.hit_test_point(Point::new(40.0, -5.0));
+    assert_eq!((hit.metrics.text_position, hit.is_inside), (5, false));
+    let hit = layout.hit_test_point(Point::new(1.0, 20.0));
+    assert_eq!((hit.metrics.text_position, hit.is_inside), (6, false));
+    assert_eq!(layout.hit_test_text_position(8).unwrap().point, Point::new(12.0, 12.0));
+    assert_eq!(layout.hit_test_text_position(15).unwrap().point, Point::new(54.0, 12.0));
+
+    //  Each line is drawn below the previous line
+    let mut display = TestDisplay { pixels: Vec::new() };
+    let mut rc = EmbedRenderContext::new(&mut display);
+    rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+    let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+    let layout = rc.text().new_text_layout(&font, "ab cd").max_width(4.0).build().unwrap();
+    rc.draw_text(&layout, (0.0, 3.0), &Color::WHITE);
+    assert_eq!(display.pixels.len(), 2 * 12);
+    assert!(display.pixels.iter().all(|&(x, y, _)| x < 4 && y < 6));
+}
+
+#[test]
+fn test_text_metrics() {
+    //  Bitmap fonts have a baseline within the character cells
+    let mut text = EmbedText::new();
+    let font = text.new_font_by_name("Font6x12", 12.0).build().unwrap();
+    let layout = text.new_text_layout(&font, "hello").build().unwrap();
+    assert_eq!((layout.ascent(), layout.descent(), layout.line_height()), (10.0, 2.0, 12.0));
+    assert_eq!(layout.image_bounds(), Rect::new(0.0, -10.0, 30.0, 2.0));
+
+    //  Bounds cover every line, and empty text has no bounds
+    let layout = text.new_text_layout(&font, "hello world foo").max_width(60.0).build().unwrap();
+    assert_eq!(layout.image_bounds(), Rect::new(0.0, -10.0, 54.0, 14.0));
+    let layout = text.new_text_layout(&font, "").build().unwrap();
+    assert_eq!(layout.image_bounds(), Rect::ZERO);
+
+    //  Text is drawn above the baseline at `pos`
+    let pixels = render(|rc| {
+        rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks).with_baseline(2)).unwrap();
+        let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "ab").build().unwrap();
+        assert_eq!(layout.image_bounds(), Rect::new(0.0, -2.0, 4.0, 1.0));
+        rc.draw_text(&layout, (10.0, 20.0), &Color::WHITE);
+    });
+    assert_eq!(pixels.len(), 12);
+    assert!(has_pixel(&pixels, 10, 18) && has_pixel(&pixels, 13, 20));
+    assert!(!has_pixel(&pixels, 10, 17) && !has_pixel(&pixels, 10, 21));
+}
+
+/// Render 2 by 3 pixel cells for each character, with the glyph in the left column and the fill in the right column
+fn render_columns(text: &str, stroke: Rgb565, fill: Option<Rgb565>, pixels: &mut dyn FnMut(&mut dyn Iterator<Item = Pixel<Rgb565>>)) {
+    let width = 2 * text.chars().count() as u32;
+    let mut glyphs = (0..3).flat_map(move |y| (0..width).filter_map(move |x| {
+        if x % 2 == 0 { Some(Pixel(UnsignedCoord::new(x, y), stroke)) }
+        else { fill.map(|fill| Pixel(UnsignedCoord::new(x, y), fill)) }
+    }));
+    pixels(&mut glyphs)
+}
+
+#[test]
+fn test_text_background() {
+    //  Only the glyphs are drawn by default
+    let green = Rgb565::from((0, 0xff, 0));
+    let pixels = render(|rc| {
+        rc.text().register_font(BitmapFont::new("Columns", 3, 2, 3, render_columns)).unwrap();
+        let font = rc.text().new_font_by_name("Columns", 3.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "ab").build().unwrap();
+        rc.draw_text(&layout, (10.0, 23.0), &Color::rgb8(0, 0xff, 0));
+    });
+    assert_eq!(pixels.len(), 6);
+    assert!(pixels.iter().all(|&(x, _, color)| x % 2 == 0 && color == green));
+
+    //  The background brush fills the character cells under the glyphs
+    let red = Rgb565::from((0xff, 0, 0));
+    let pixels = render(|rc| {
+        rc.text().register_font(BitmapFont::new("Columns", 3, 2, 3, render_columns)).unwrap();
+        let font = rc.text().new_font_by_name("Columns", 3.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "ab").build().unwrap();
+        rc.draw_text_with_background(&layout, (10.0, 23.0), &Color::rgb8(0, 0xff, 0), &Color::rgb8(0xff, 0, 0));
+    });
+    assert_eq!(pixels.len(), 12 + 6);
+    assert!(pixels[..12].iter().all(|&(x, y, color)| x >= 10 && x < 14 && y >= 20 && y < 23 && color == red));
+    assert!(pixels[12..].iter().all(|&(x, _, color)| x % 2 == 0 && color == green));
+}
+
+#[test]
+fn test_text_alignment() {
+    //  Lines are aligned within the max width
+    let mut text = EmbedText::new();
+    let font = text.new_font_by_name("Font6x12", 12.0).build().unwrap();
+    let layout = text.new_text_layout(&font, "hello").max_width(60.0).alignment(TextAlignment::Center).build().unwrap();
+    assert_eq!(layout.image_bounds(), Rect::new(15.0, -10.0, 45.0, 2.0));
+    assert_eq!(layout.hit_test_text_position(0).unwrap().point, Point::new(15.0, 0.0));
+    let hit = layout.hit_test_point(Point::new(20.0, 0.0));
+    assert_eq!((hit.metrics.text_position, hit.is_inside), (1, true));
+    let layout = text.new_text_layout(&font, "hello").max_width(60.0).alignment(TextAlignment::Right).build().unwrap();
+    assert_eq!(layout.hit_test_text_position(5).unwrap().point, Point::new(60.0, 0.0));
+
+    //  Without a max width, lines are aligned within the widest line
+    let layout = text.new_text_layout(&font, "hello\nfoo").alignment(TextAlignment::Right).build().unwrap();
+    assert_eq!(layout.hit_test_text_position(6).unwrap().point, Point::new(12.0, 12.0));
+
+    //  Text that overflows is truncated at a grapheme boundary, with an ellipsis that fits
+    let layout = text.new_text_layout(&font, "hello world").max_width(40.0).ellipsis(true).build().unwrap();
+    assert_eq!(layout.text.as_str(), "hel...");
+    assert_eq!(layout.line_count(), 1);
+    let layout = text.new_text_layout(&font, "hello\nworld").max_width(60.0).ellipsis(true).build().unwrap();
+    assert_eq!(layout.text.as_str(), "hello...");
+    let layout = text.new_text_layout(&font, "hello").max_width(30.0).ellipsis(true).build().unwrap();
+    assert_eq!(layout.text.as_str(), "hello");
+    let layout = text.new_text_layout(&font, "hello\n").max_width(60.0).ellipsis(true).build().unwrap();
+    assert_eq!((layout.text.as_str(), layout.line_count()), ("hello", 1));
+
+    //  Aligned lines are drawn at their aligned position
+    let pixels = render(|rc| {
+        rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+        let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "ab").max_width(10.0).alignment(TextAlignment::Right).build().unwrap();
+        rc.draw_text(&layout, (10.0, 23.0), &Color::WHITE);
+    });
+    assert_eq!(pixels.len(), 12);
+    assert!(pixels.iter().all(|&(x, y, _)| x >= 16 && x < 20 && y >= 20 && y < 23));
+}
+
+#[test]
+fn test_text_position() {
+    //  Text at fractional and negative positions is drawn on the same pixels as a fill at the same coordinates
+    for &(x, y) in &[(10.6, 23.4), (10.4, 23.6), (-0.6, 3.0)] {
+        let mut text = render(|rc| {
+            rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+            let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+            let layout = rc.text().new_text_layout(&font, "ab").build().unwrap();
+            rc.draw_text(&layout, (x, y), &Color::WHITE);
+        });
+        let mut fill = render(|rc| rc.fill(Rect::new(x, y - 3.0, x + 4.0, y), &Color::WHITE));
+        text.sort_by_key(|&(x, y, _)| (y, x));
+        fill.sort_by_key(|&(x, y, _)| (y, x));
+        assert_eq!(text, fill);
+    }
+
+    //  Transformed text covers every scaled glyph pixel
+    let pixels = render(|rc| {
+        rc.text().register_font(BitmapFont::new("Blocks", 3, 2, 3, render_blocks)).unwrap();
+        let font = rc.text().new_font_by_name("Blocks", 3.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "ab").build().unwrap();
+        rc.transform(Affine::scale(2.0));
+        rc.draw_text(&layout, (5.0, 10.0), &Color::WHITE);
+    });
+    assert_eq!(pixels.len(), 4 * 12);
+    assert!(pixels.iter().all(|&(x, y, _)| x >= 10 && x < 18 && y >= 14 && y < 20));
+}
+
+#[test]
+fn test_batch_sparse_blocks() {
+    //  Vertical strokes are batched into a block each, instead of a block per pixel
+    let white = Rgb565(0xffff);
+    let pixels = (0..4).flat_map(|y| [0, 3].iter().map(move |&x| Pixel(UnsignedCoord::new(x, y), white)));
+    let blocks: Vec<_> = batch::to_blocks(batch::to_rows(pixels)).collect();
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks.iter().all(|block| block.x_left == block.x_right && block.y_top == 0 && block.y_bottom == 3 && block.colors.len() == 4));
+
+    //  Pixels that are drawn again are batched after the earlier pixels
+    let black = Rgb565(0);
+    let pixels = vec![Pixel(UnsignedCoord::new(0, 0), white), Pixel(UnsignedCoord::new(2, 0), black), Pixel(UnsignedCoord::new(0, 0), black)];
+    let blocks: Vec<_> = batch::to_blocks(batch::to_rows(pixels.into_iter())).collect();
+    let first = blocks.iter().position(|block| block.x_left == 0 && block.colors[0] == white.0).unwrap();
+    let last = blocks.iter().position(|block| block.x_left == 0 && block.colors[0] == black.0).unwrap();
+    assert!(first < last);
+
+    //  Blocks are returned when too many are pending, and every pixel is batched
+    let pixels = (0..4).flat_map(|y| (0..10).step_by(2).map(move |x| Pixel(UnsignedCoord::new(x, y), white)));
+    let blocks: Vec<_> = batch::to_blocks(batch::to_rows(pixels)).collect();
+    assert_eq!(blocks.iter().map(|block| block.colors.len()).sum::<usize>(), 20);
+
+    //  Any target may draw the blocks, including columns beyond 255
+    let pixels = (0..3).flat_map(|y| (300..302).map(move |x| Pixel(UnsignedCoord::new(x, y), white)));
+    let mut windows = Vec::new();
+    batch::draw_blocks(pixels, |x_left, y_top, x_right, y_bottom, colors| -> Result<(), ()> {
+        windows.push((x_left, y_top, x_right, y_bottom, colors.len()));
+        Ok(())
+    }).unwrap();
+    assert_eq!(windows, vec![(300, 0, 301, 2, 6)]);
+}
+
+/// Append the big-endian bytes of the 16-bit value
+#[cfg(feature = "truetype")]
+fn push16(data: &mut Vec<u8>, value: i32) {
+    data.extend_from_slice(&(value as u16).to_be_bytes());
+}
+
+/// Return a tiny TrueType font with 16 units per em. The glyphs 'A' and 'V' are 8 x 10 squares on
+/// the baseline that advance by 10 units, and the pair "AV" is kerned by -2 units.
+/// The font is made once, because outline fonts borrow `'static` font data.
+#[cfg(feature = "truetype")]
+fn tiny_font() -> &'static [u8] {
+    static FONT: std::sync::OnceLock<Vec<u8>> = std::sync::OnceLock::new();
+    FONT.get_or_init(make_tiny_font)
+}
+
+/// Return the tiny TrueType font as an outline font named "Tiny". The font is made once, because
+/// outline fonts are registered as `'static` references.
+#[cfg(feature = "truetype")]
+fn tiny_outline_font() -> &'static crate::OutlineFont {
+    static FONT: std::sync::OnceLock<crate::OutlineFont> = std::sync::OnceLock::new();
+    FONT.get_or_init(|| crate::OutlineFont::new("Tiny", tiny_font()).unwrap())
+}
+
+/// Return the font data of the tiny TrueType font
+#[cfg(feature = "truetype")]
+fn make_tiny_font() -> Vec<u8> {
+    let mut head = vec![0; 54];
+    head[..4].copy_from_slice(&[0, 1, 0, 0]);
+    head[12..16].copy_from_slice(&[0x5f, 0x0f, 0x3c, 0xf5]);
+    head[18..20].copy_from_slice(&[0, 16]);  //  Units per em, with short loca offsets
+    let mut hhea = vec![0, 1, 0, 0];
+    for value in &[12, -4, 0, 10] { push16(&mut hhea, *value); }  //  Ascender, descender, line gap, max advance
+    hhea.resize(34, 0);
+    push16(&mut hhea, 3);  //  Horizontal metrics
+    let maxp = vec![0, 0, 0x50, 0, 0, 3];
+    let mut hmtx = Vec::new();
+    for value in &[0, 0, 10, 0, 10, 0] { push16(&mut hmtx, *value); }
+    let mut glyf = Vec::new();
+    for _ in 0..2 {
+        //  One contour of 4 points on the curve, with 16-bit coordinate deltas
+        for value in &[1, 0, 0, 8, 10, 3, 0] { push16(&mut glyf, *value); }
+        glyf.extend_from_slice(&[1, 1, 1, 1]);
+        for value in &[0, 8, 0, -8, 0, 0, 10, 0, 0] { push16(&mut glyf, *value); }  //  Padded to 36 bytes
+    }
+    let mut loca = Vec::new();
+    for value in &[0, 0, 18, 36] { push16(&mut loca, *value); }
+    let mut cmap = Vec::new();
+    for value in &[0, 1, 3, 1, 0, 12, 6, 54, 0, 'A' as i32, 22] { push16(&mut cmap, *value); }
+    for c in b'A'..=b'V' { push16(&mut cmap, match c { b'A' => 1, b'V' => 2, _ => 0 }); }
+    let mut kern = Vec::new();
+    for value in &[0, 1, 0, 20, 0x0001, 1, 6, 0, 0, 1, 2, -2] { push16(&mut kern, *value); }
+
+    //  Tables are sorted by tag
+    let tables: [(&[u8; 4], &Vec<u8>); 8] = [
+        (b"cmap", &cmap), (b"glyf", &glyf), (b"head", &head), (b"hhea", &hhea),
+        (b"hmtx", &hmtx), (b"kern", &kern), (b"loca", &loca), (b"maxp", &maxp),
+    ];
+    let mut font = vec![0, 1, 0, 0];
+    for value in &[tables.len() as i32, 0, 0, 0] { push16(&mut font, *value); }
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, table) in tables.iter() {
+        font.extend_from_slice(*tag);
+        font.extend_from_slice(&[0; 4]);
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += (table.len() + 3) & !3;
+    }
+    for (_, table) in tables.iter() {
+        font.extend_from_slice(table);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+    font
+}
+
+#[cfg(feature = "truetype")]
+#[test]
+fn test_outline_font() {
+    //  Outline fonts are scaled to the size, and preferred by name
+    let mut text = EmbedText::new();
+    assert!(crate::OutlineFont::new("Tiny", &[0; 12]).is_err());
+    text.register_outline_font(tiny_outline_font()).unwrap();
+    let font = text.new_font_by_name("Font6x8", 8.0).build().unwrap();
+    assert_eq!(font.name(), "Font6x8");
+    let font = text.new_font_by_name("tiny", 32.0).build().unwrap();
+    assert_eq!(font.name(), "Tiny");
+
+    //  Width includes the kerning between glyphs
+    assert_eq!(text.new_text_layout(&font, "A").build().unwrap().width(), 20.0);
+    assert_eq!(text.new_text_layout(&font, "AV").build().unwrap().width(), 36.0);
+    assert_eq!(text.new_text_layout(&font, "VA").build().unwrap().width(), 40.0);
+
+    //  Hit tests use the kerned advance widths
+    let layout = text.new_text_layout(&font, "AV").build().unwrap();
+    assert_eq!(layout.hit_test_text_position(1).unwrap().point.x, 16.0);
+    assert_eq!(layout.hit_test_point(Point::new(25.0, 0.0)).metrics.text_position, 1);
+    assert_eq!(layout.hit_test_point(Point::new(27.0, 0.0)).metrics.text_position, 2);
+
+    //  Metrics are scaled from font units
+    let font = text.new_font_by_name("tiny", 16.0).build().unwrap();
+    let layout = text.new_text_layout(&font, "AV").build().unwrap();
+    assert_eq!((layout.ascent(), layout.descent(), layout.line_height()), (12.0, 4.0, 16.0));
+    assert_eq!(layout.image_bounds(), Rect::new(0.0, -10.0, 16.0, 0.0));
+
+    //  Glyphs are filled on the baseline
+    let pixels = render(|rc| {
+        rc.text().register_outline_font(tiny_outline_font()).unwrap();
+        let font = rc.text().new_font_by_name("Tiny", 16.0).build().unwrap();
+        let layout = rc.text().new_text_layout(&font, "AV").build().unwrap();
+        rc.draw_text(&layout, (10.0, 32.0), &Color::WHITE);
+    });
+    assert_eq!(pixels.len(), 160);
+    assert!(has_pixel(&pixels, 10, 22) && has_pixel(&pixels, 25, 31));
+    assert!(!has_pixel(&pixels, 10, 21) && !has_pixel(&pixels, 26, 22) && !has_pixel(&pixels, 10, 32));
+}
@@ -1,62 +1,296 @@
+use core::marker::PhantomData;
 use core::str::FromStr;
+use embedded_graphics::{
+    prelude::*,
+    fonts::{ self, Font as EFont },
+    pixelcolor::Rgb565,
+};
 use heapless::{
+    ArrayLength,
     String,
+    Vec,
     consts::*,
 };
-use piet::kurbo::{ Point, };
+use piet::kurbo::{ Point, Rect, Vec2 };
 use piet::{
     Error, 
     Font, FontBuilder,
-    HitTestPoint, HitTestTextPosition, 
-    Text, TextLayout, TextLayoutBuilder,
+    HitTestMetrics, HitTestPoint, HitTestTextPosition, 
+    LineMetric, Text, TextAlignment, TextLayout, TextLayoutBuilder,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use xi_unicode::LineBreakIterator;
+use crate::grapheme::{ get_grapheme_boundaries, point_x_in_grapheme };
+#[cfg(feature = "truetype")]
+use crate::truetype::{ OutlineFont, ScaledFont };
 
 ////TODO: Sync with druid/src/env.rs
 ////pub type FontType<'a> = fonts::Font12x16::<'a, Rgb565>;
-pub const FONT_WIDTH:  u16  = 12;
 //pub const FONT_HEIGHT: u16  = 16;
 //pub const FONT_NAME:   &str = &"Font12x16";
 //pub const TEXT_SIZE_NORMAL: f64 = FONT_HEIGHT as f64;
 
-/// Right now, we don't need any state, as the "toy text API" treats the
-/// access to system font information as a global. This will change.
-pub struct EmbedText;
+/// Maximum number of bytes in the text of a layout, unless the capacity is set by `EmbedText::with_capacity()`
+pub type DefaultTextCapacity = U64;
+
+/// Ellipsis that ends truncated text. The embedded-graphics fonts have no "…" character.
+const ELLIPSIS: &str = "...";
+/// Characters that force a line break, as in Unicode line breaking
+const HARD_BREAKS: [char; 7] = ['\n', '\r', '\u{0b}', '\u{0c}', '\u{85}', '\u{2028}', '\u{2029}'];
+
+/// Maximum number of bitmap fonts that may be registered, including the built-in fonts
+type MaxFonts = U8;
+
+/// Maximum number of outline fonts that may be registered
+#[cfg(feature = "truetype")]
+type MaxOutlineFonts = U2;
+
+/// Renders the text at the origin with an embedded-graphics font, stroking the glyphs with the
+/// stroke colour and filling the glyph cells with the fill colour, then passes the pixels to the callback
+pub type RenderFn = fn(
+    text:   &str, 
+    stroke: Rgb565, 
+    fill:   Option<Rgb565>, 
+    pixels: &mut dyn FnMut(&mut dyn Iterator<Item = Pixel<Rgb565>>)
+);
+
+/// Define a `RenderFn` that renders text with the embedded-graphics font
+macro_rules! render_fn {
+    ($font:ident) => {{
+        fn render(text: &str, stroke: Rgb565, fill: Option<Rgb565>, pixels: &mut dyn FnMut(&mut dyn Iterator<Item = Pixel<Rgb565>>)) {
+            let mut glyphs = fonts::$font::<Rgb565>::render_str(text)
+                .stroke(Some(stroke))
+                .fill(fill)
+                .into_iter();
+            pixels(&mut glyphs)
+        }
+        render as RenderFn
+    }};
+}
+
+/// Monospace bitmap font, registered under a name and pixel size
+#[derive(Clone, Copy)]
+pub struct BitmapFont {
+    /// Name for selecting the font in `new_font_by_name()`
+    name:     &'static str,
+    /// Size in pixels for selecting the font in `new_font_by_name()`
+    size:     u16,
+    /// Width of each character in pixels
+    width:    u16,
+    /// Height of each character in pixels
+    height:   u16,
+    /// Distance in pixels from the top of each character to the baseline
+    baseline: u16,
+    /// Function that renders the glyphs
+    render:   RenderFn,
+}
+
+impl BitmapFont {
+    /// Create a bitmap font with the name and pixel size, whose characters are `width` by `height` pixels.
+    /// `render` renders text with the embedded-graphics font. The baseline is at the bottom of the
+    /// characters, unless it's set by `with_baseline()`.
+    pub fn new(name: &'static str, size: u16, width: u16, height: u16, render: RenderFn) -> BitmapFont {
+        BitmapFont { name, size, width, height, baseline: height, render }
+    }
+
+    /// Return the font with the baseline `baseline` pixels below the top of each character
+    pub fn with_baseline(mut self, baseline: u16) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    /// Name of the font
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Size of the font in pixels
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Width of each character in pixels
+    pub fn char_width(&self) -> u16 {
+        self.width
+    }
+
+    /// Height of each character in pixels
+    pub fn char_height(&self) -> u16 {
+        self.height
+    }
+
+    /// Distance in pixels from the top of each character to the baseline
+    pub fn baseline(&self) -> u16 {
+        self.baseline
+    }
+
+    /// Render the text at the origin and pass the pixels to the callback
+    pub fn render(&self, text: &str, stroke: Rgb565, fill: Option<Rgb565>, pixels: &mut dyn FnMut(&mut dyn Iterator<Item = Pixel<Rgb565>>)) {
+        (self.render)(text, stroke, fill, pixels)
+    }
+}
 
-pub struct EmbedFont;
+/// Text factory that holds the registry of fonts. The embedded-graphics fonts
+/// `Font6x8`, `Font6x12`, `Font8x16` and `Font12x16` are registered under their names.
+/// Text layouts hold up to `N` bytes of text.
+pub struct EmbedText<N: ArrayLength<u8> = DefaultTextCapacity> {
+    /// Registered bitmap fonts, in order of registration
+    fonts:         Vec<BitmapFont, MaxFonts>,
+    /// Registered outline fonts, in order of registration
+    #[cfg(feature = "truetype")]
+    outline_fonts: Vec<&'static OutlineFont, MaxOutlineFonts>,
+    /// Capacity of the text layouts
+    capacity:      PhantomData<N>,
+}
+
+/// Font selected from the registry
+#[derive(Clone)]
+pub enum EmbedFont {
+    /// Monospace bitmap font
+    Bitmap(BitmapFont),
+    /// TrueType outline font, scaled to the size
+    #[cfg(feature = "truetype")]
+    Outline(ScaledFont),
+}
 
 pub struct EmbedFontBuilder {
+    /// Font selected by name and size, or `None` if no fonts are registered
+    font: Option<EmbedFont>,
     ////family: String,
     ////weight: FontWeight,
     ////slant: FontSlant,
     ////size: f64,
 }
 
-pub struct EmbedTextLayout {
+pub struct EmbedTextLayout<N: ArrayLength<u8> = DefaultTextCapacity> {
     ////font: ScaledFont,
+    font: EmbedFont,  ////
     ////pub text: ArrayString::<[u8; 20]>,
-    pub text: String::<U20>,
+    pub text: String::<N>,
+    /// Max width of each line, or infinity if the text isn't wrapped
+    max_width: f64,
+    /// Alignment of each line within the max width
+    alignment: TextAlignment,
 }
 
-pub struct EmbedTextLayoutBuilder(EmbedTextLayout);
+/// Iterator over the lines of a text layout. The text is wrapped at the last line break opportunity
+/// that fits the max width, as the lines are iterated, so no memory is allocated for the lines.
+pub(crate) struct Lines<'a> {
+    /// Font for measuring the lines
+    font:      &'a EmbedFont,
+    /// Text of the layout
+    text:      &'a str,
+    /// Max width of each line
+    max_width: f64,
+    /// Text position of the next line, or `None` after the last line
+    start:     Option<usize>,
+    /// Distance from the top of the first line to the top of the next line
+    y_offset:  f64,
+}
+
+/// Builder for a text layout
+pub struct EmbedTextLayoutBuilder<N: ArrayLength<u8> = DefaultTextCapacity> {
+    /// Text layout, or `None` if the text doesn't fit in the layout
+    layout:   Option<EmbedTextLayout<N>>,
+    /// True if the text is truncated to one line with an ellipsis
+    ellipsis: bool,
+}
 
 impl EmbedText {
-    /// Create a new factory that satisfies the piet `Text` trait.
-    ///
-    /// No state is needed for now because the current implementation is just
-    /// toy text, but that will change when proper text is implemented.
+    /// Create a new factory that satisfies the piet `Text` trait, with the
+    /// embedded-graphics fonts registered. Text layouts hold up to `DefaultTextCapacity` bytes.
     pub fn new() -> EmbedText {
-        EmbedText
+        EmbedText::with_capacity()
     }
 }
 
-impl Text for EmbedText {
+impl<N: ArrayLength<u8>> EmbedText<N> {
+    /// Create a new factory whose text layouts hold up to `N` bytes, like `EmbedText::<U256>::with_capacity()`,
+    /// with the embedded-graphics fonts registered.
+    pub fn with_capacity() -> EmbedText<N> {
+        let mut text = EmbedText {
+            fonts:         Vec::new(),
+            #[cfg(feature = "truetype")]
+            outline_fonts: Vec::new(),
+            capacity:      PhantomData,
+        };
+        let builtin = [
+            BitmapFont::new("Font6x8",    8,  6,  8, render_fn!(Font6x8)).with_baseline(7),
+            BitmapFont::new("Font6x12",  12,  6, 12, render_fn!(Font6x12)).with_baseline(10),
+            BitmapFont::new("Font8x16",  16,  8, 16, render_fn!(Font8x16)).with_baseline(12),
+            BitmapFont::new("Font12x16", 16, 12, 16, render_fn!(Font12x16)).with_baseline(12),
+        ];
+        for font in builtin.iter() {
+            text.register_font(*font).expect("register font fail");
+        }
+        text
+    }
+
+    /// Return a factory whose text layouts hold up to `M` bytes, with the same registered fonts
+    pub fn resize<M: ArrayLength<u8>>(self) -> EmbedText<M> {
+        EmbedText {
+            fonts:         self.fonts,
+            #[cfg(feature = "truetype")]
+            outline_fonts: self.outline_fonts,
+            capacity:      PhantomData,
+        }
+    }
+
+    /// Register a bitmap font, to be selected by its name and size in `new_font_by_name()`.
+    ///
+    /// Returns an error if too many fonts are registered.
+    pub fn register_font(&mut self, font: BitmapFont) -> Result<(), Error> {
+        self.fonts.push(font)
+            .map_err(|_| Error {})  //  Too many fonts
+    }
+
+    /// Register a TrueType outline font, to be selected by its name in `new_font_by_name()`
+    /// and scaled to the size. The font must live for the rest of the program, e.g. in a `static`.
+    ///
+    /// Returns an error if too many outline fonts are registered.
+    #[cfg(feature = "truetype")]
+    pub fn register_outline_font(&mut self, font: &'static OutlineFont) -> Result<(), Error> {
+        self.outline_fonts.push(font)
+            .map_err(|_| Error {})  //  Too many outline fonts
+    }
+
+    /// Return the registered font whose size is closest to the size, preferring fonts with
+    /// the name. If no font has the name, any font may be returned.
+    fn select_font(&self, name: &str, size: f64) -> Option<EmbedFont> {
+        self.closest_font(|font| font.eq_ignore_ascii_case(name), size)
+            .or_else(|| self.closest_font(|_| true, size))
+    }
+
+    /// Return the font whose size is closest to the size, among the fonts whose names are accepted.
+    /// Outline fonts are scaled to the size, so they are always the closest.
+    fn closest_font(&self, accept: impl Fn(&str) -> bool, size: f64) -> Option<EmbedFont> {
+        #[cfg(feature = "truetype")]
+        {
+            if let Some(font) = self.outline_fonts.iter().find(|font| accept(font.name())) {
+                return Some(EmbedFont::Outline(font.with_size(size)));
+            }
+        }
+        let mut best: Option<&BitmapFont> = None;
+        for font in self.fonts.iter().filter(|font| accept(font.name)) {
+            let distance = (font.size as f64 - size).abs();
+            match best {
+                Some(b) if (b.size as f64 - size).abs() <= distance => {}
+                _ => best = Some(font),
+            }
+        }
+        best.map(|font| EmbedFont::Bitmap(*font))
+    }
+}
+
+impl<N: ArrayLength<u8>> Text for EmbedText<N> {
     type Font = EmbedFont;
     type FontBuilder = EmbedFontBuilder;
-    type TextLayout = EmbedTextLayout;
-    type TextLayoutBuilder = EmbedTextLayoutBuilder;
+    type TextLayout = EmbedTextLayout<N>;
+    type TextLayoutBuilder = EmbedTextLayoutBuilder<N>;
 
-    fn new_font_by_name(&mut self, _name: &str, _size: f64) -> Self::FontBuilder {
+    fn new_font_by_name(&mut self, name: &str, size: f64) -> Self::FontBuilder {
         EmbedFontBuilder {
+            font: self.select_font(name, size),
             ////family: name,
             ////size: size.round_into(),
             ////weight: FontWeight::Normal,
@@ -64,12 +298,17 @@ impl Text for EmbedText {
         }
     }
 
-    fn new_text_layout(&mut self, _font: &Self::Font, text: &str) -> Self::TextLayoutBuilder {
-        let text_layout = EmbedTextLayout {
-            ////font: font.0.clone(),
-            text: String::<U20>::from_str(text).expect("text layout fail"),
-        };
-        EmbedTextLayoutBuilder(text_layout)
+    fn new_text_layout(&mut self, font: &Self::Font, text: &str) -> Self::TextLayoutBuilder {
+        ////text: String::<U20>::from_str(text).expect("text layout fail"),
+        let text_layout = String::<N>::from_str(text).ok()  ////
+            .map(|text| EmbedTextLayout {
+                ////font: font.0.clone(),
+                font: font.clone(),  ////
+                text,
+                max_width: f64::INFINITY,  ////
+                alignment: TextAlignment::Left,  ////
+            });
+        EmbedTextLayoutBuilder { layout: text_layout, ellipsis: false }  ////
     }
 }
 
@@ -78,7 +317,8 @@ impl FontBuilder for EmbedFontBuilder {
     type Out = EmbedFont;
 
     fn build(self) -> Result<Self::Out, Error> {
-        Ok(EmbedFont)
+        self.font
+            .ok_or(Error {})  //  No fonts registered
         /*
         let font_face = FontFace::toy_create(&self.family, self.slant, self.weight);
         let font_matrix = scale_matrix(self.size);
@@ -90,150 +330,419 @@ impl FontBuilder for EmbedFontBuilder {
     }
 }
 
-impl Font for EmbedFont {}
+impl EmbedFont {
+    /// Name of the font
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmbedFont::Bitmap(font) => font.name(),
+            #[cfg(feature = "truetype")]
+            EmbedFont::Outline(font) => font.name(),
+        }
+    }
 
-impl TextLayoutBuilder for EmbedTextLayoutBuilder {
-    type Out = EmbedTextLayout;
+    /// Distance in pixels from the top of the line to the baseline
+    fn ascent(&self) -> f64 {
+        match self {
+            EmbedFont::Bitmap(font) => font.baseline as f64,
+            #[cfg(feature = "truetype")]
+            EmbedFont::Outline(font) => font.ascent(),
+        }
+    }
 
-    fn build(self) -> Result<Self::Out, Error> {
-        Ok(self.0)
+    /// Distance in pixels from the baseline to the bottom of the line
+    fn descent(&self) -> f64 {
+        match self {
+            EmbedFont::Bitmap(font) => font.height as f64 - font.baseline as f64,
+            #[cfg(feature = "truetype")]
+            EmbedFont::Outline(font) => font.descent(),
+        }
     }
-}
 
-impl TextLayout for EmbedTextLayout {
-    fn width(&self) -> f64 {
-        (self.text.len() * FONT_WIDTH as usize) as f64
-        ////self.font.text_extents(&self.text).x_advance
+    /// Distance in pixels from the top of one line to the top of the next line
+    fn line_height(&self) -> f64 {
+        match self {
+            EmbedFont::Bitmap(font) => font.height as f64,
+            #[cfg(feature = "truetype")]
+            EmbedFont::Outline(font) => font.line_height(),
+        }
     }
 
-    // first assume one line.
-    // TODO do with lines
-    fn hit_test_point(&self, _point: Point) -> HitTestPoint {
-        return HitTestPoint::default();  ////  TODO: Hit test with text not supported
+    /// Bounding box of the glyphs of the text in pixels, with the baseline at the origin,
+    /// or `None` if nothing is drawn. Bitmap fonts are bounded by their character cells.
+    fn bounds(&self, text: &str) -> Option<Rect> {
+        match self {
+            EmbedFont::Bitmap(_) if text.is_empty() => None,
+            EmbedFont::Bitmap(_) => Some(Rect::new(0., -self.ascent(), self.width(text), self.descent())),
+            #[cfg(feature = "truetype")]
+            EmbedFont::Outline(font) => font.glyphs(text)
+                .filter_map(|(glyph, x)| font.glyph_bounds(glyph).map(|rect| rect + Vec2::new(x, 0.)))
+                .fold(None, |bounds, rect| Some(bounds.map_or(rect, |bounds: Rect| bounds.union(rect)))),
+        }
+    }
 
-        /* ////  TODO
-        // internal logic is using grapheme clusters, but return the text position associated
-        // with the border of the grapheme cluster.
+    /// Width of the text in pixels
+    fn width(&self, text: &str) -> f64 {
+        self.position(text, text.len())
+    }
 
-        // null case
-        if self.text.len() == 0 {
-            return HitTestPoint::default();
+    /// Horizontal position in pixels of the character at the byte index of the text
+    fn position(&self, text: &str, index: usize) -> f64 {
+        match self {
+            EmbedFont::Bitmap(font) => (text[..index].chars().count() * font.width as usize) as f64,
+            #[cfg(feature = "truetype")]
+            EmbedFont::Outline(font) => font.position(text, index),
         }
+    }
+}
 
-        // get bounds
-        // TODO handle if string is not null yet count is 0?
-        let end = UnicodeSegmentation::graphemes(self.text.as_str(), true).count() - 1;
-        let end_bounds = match self.get_grapheme_boundaries(end) {
-            Some(bounds) => bounds,
-            None => return HitTestPoint::default(),
-        };
+impl Font for EmbedFont {}
 
-        let start = 0;
-        let start_bounds = match self.get_grapheme_boundaries(start) {
-            Some(bounds) => bounds,
-            None => return HitTestPoint::default(),
-        };
+impl<N: ArrayLength<u8>> TextLayoutBuilder for EmbedTextLayoutBuilder<N> {
+    type Out = EmbedTextLayout<N>;
 
-        // first test beyond ends
-        if point.x > end_bounds.trailing {
-            let mut res = HitTestPoint::default();
-            res.metrics.text_position = self.text.len();
-            return res;
-        }
-        if point.x <= start_bounds.leading {
-            return HitTestPoint::default();
+    fn max_width(mut self, width: f64) -> Self {
+        if let Some(layout) = &mut self.layout {
+            layout.max_width = width;
         }
+        self
+    }
 
-        // then test the beginning and end (common cases)
-        if let Some(hit) = point_x_in_grapheme(point.x, &start_bounds) {
-            return hit;
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        if let Some(layout) = &mut self.layout {
+            layout.alignment = alignment;
         }
-        if let Some(hit) = point_x_in_grapheme(point.x, &end_bounds) {
-            return hit;
+        self
+    }
+
+    fn ellipsis(mut self, ellipsis: bool) -> Self {
+        self.ellipsis = ellipsis;
+        self
+    }
+
+    fn build(self) -> Result<Self::Out, Error> {
+        let mut layout = self.layout
+            .ok_or(Error {})?;  //  Text is too long for the layout
+        if self.ellipsis {
+            layout.truncate();
         }
+        Ok(layout)
+    }
+}
 
-        // Now that we know it's not beginning or end, begin binary search.
-        // Iterative style
-        let mut left = start;
-        let mut right = end;
-        loop {
-            // pick halfway point
-            let middle = left + ((right - left) / 2);
+impl<N: ArrayLength<u8>> EmbedTextLayout<N> {
+    /// The font for rendering the text
+    pub fn font(&self) -> &EmbedFont {
+        &self.font
+    }
 
-            let grapheme_bounds = match self.get_grapheme_boundaries(middle) {
-                Some(bounds) => bounds,
-                None => return HitTestPoint::default(),
-            };
+    /// Iterate over the wrapped lines of text
+    pub(crate) fn lines(&self) -> Lines<'_> {
+        Lines { font: &self.font, text: &self.text, max_width: self.max_width, start: Some(0), y_offset: 0. }
+    }
 
-            if let Some(hit) = point_x_in_grapheme(point.x, &grapheme_bounds) {
-                return hit;
-            }
+    /// Text of the line without its trailing whitespace
+    pub(crate) fn visible_text(&self, line: &LineMetric) -> &str {
+        &self.text[line.start_offset..line.end_offset - line.trailing_whitespace]
+    }
 
-            // since it's not a hit, check if closer to start or finish
-            // and move the appropriate search boundary
-            if point.x < grapheme_bounds.leading {
-                right = middle;
-            } else if point.x > grapheme_bounds.trailing {
-                left = middle + 1;
-            } else {
-                unreachable!("hit_test_point conditional is exhaustive");
-            }
-        }
-        */ ////
+    /// Horizontal position of the start of the line, aligned within the max width,
+    /// or within the widest line if there's no max width
+    pub(crate) fn line_x(&self, line: &LineMetric) -> f64 {
+        let factor = match self.alignment {
+            TextAlignment::Left   => return 0.,
+            TextAlignment::Center => 0.5,
+            TextAlignment::Right  => 1.,
+        };
+        let width = if self.max_width.is_finite() { self.max_width } else { self.width() };
+        (width - self.font.width(self.visible_text(line))) * factor
     }
 
-    fn hit_test_text_position(&self, _text_position: usize) -> Option<HitTestTextPosition> {
-        return Some(HitTestTextPosition::default());  ////  TODO: Hit test with text not supported
+    /// Bounds of the characters of the line without its trailing whitespace, from the top to the bottom
+    /// of the line, relative to the first baseline
+    pub(crate) fn line_bounds(&self, line: &LineMetric) -> Rect {
+        let x = self.line_x(line);
+        let top = line.y_offset - self.font.ascent();
+        Rect::new(x, top, x + self.font.width(self.visible_text(line)), top + line.height)
+    }
 
-        /*  ////  TODO
-        // Using substrings, but now with unicode grapheme awareness
+    /// Truncate the text to the first line, if it's wider than the max width or if there are more lines.
+    /// The truncated text ends with an ellipsis, and is cut at a grapheme boundary so that it fits.
+    fn truncate(&mut self) {
+        let end = LineBreakIterator::new(&self.text)
+            .find(|(_, hard)| *hard)
+            .map_or(self.text.len(), |(offset, _)| offset);
+        let line = &self.text[..end];
+        if end == self.text.len() && self.font.width(line.trim_end()) <= self.max_width {
+            //  Remove a trailing hard break, which would start an empty line
+            while self.text.ends_with(&HARD_BREAKS[..]) { self.text.pop(); }
+            return;
+        }
 
-        let text_len = self.text.len();
+        //  Keep the most graphemes that fit with the ellipsis, in the max width and in the capacity
+        let ellipsis_width = self.font.width(ELLIPSIS);
+        let capacity = self.text.capacity();
+        let kept = line.grapheme_indices(true)
+            .map(|(index, _)| index)
+            .chain(Some(line.len()))
+            .rev()
+            .map(|index| line[..index].trim_end())
+            .find(|kept| kept.len() + ELLIPSIS.len() <= capacity && self.font.width(kept) + ellipsis_width <= self.max_width)
+            .unwrap_or("");
+        let mut text = String::<N>::from_str(kept).unwrap_or_default();
+        text.push_str(ELLIPSIS).ok();
+        self.text = text;
+    }
 
-        if text_position == 0 {
-            return Some(HitTestTextPosition::default());
+    /// Return the line that contains the `y` coordinate, relative to the first baseline. Points above
+    /// or below the text hit the first or last line, and the flag is false.
+    fn line_at(&self, y: f64) -> (LineMetric, bool) {
+        let y = y + self.ascent();
+        let mut lines = self.lines();
+        let mut line = lines.next().unwrap_or_default();
+        while y >= line.y_offset + line.height {
+            match lines.next() {
+                Some(next) => line = next,
+                None => return (line, false),
+            }
         }
+        (line, y >= 0.)
+    }
+}
 
-        if text_position as usize >= text_len {
-            return Some(HitTestTextPosition {
-                point: Point {
-                    x: self.font.text_extents(&self.text).x_advance,
-                    y: 0.0,
-                },
-                metrics: HitTestMetrics {
-                    text_position: text_len,
-                },
-            });
+impl<'a> Iterator for Lines<'a> {
+    type Item = LineMetric;
+
+    fn next(&mut self) -> Option<LineMetric> {
+        let start = self.start?;
+        let rest = &self.text[start..];
+
+        //  Break at the last opportunity that fits, or at a hard break. A word that
+        //  doesn't fit in an empty line overflows the line.
+        let mut end = 0;
+        for (offset, hard) in LineBreakIterator::new(rest) {
+            if end > 0 && self.font.width(rest[..offset].trim_end()) > self.max_width {
+                break;
+            }
+            end = offset;
+            if hard { break; }
         }
+        let line = &rest[..end];
+        let metric = LineMetric {
+            start_offset:        start,
+            end_offset:          start + end,
+            trailing_whitespace: line.len() - line.trim_end().len(),
+            baseline:            self.font.ascent(),
+            height:              self.font.line_height(),
+            y_offset:            self.y_offset,
+        };
+        self.y_offset += metric.height;
+        //  A hard break at the end of the text is followed by an empty line
+        self.start =
+            if start + end < self.text.len() || line.ends_with(&HARD_BREAKS[..]) { Some(start + end) }
+            else { None };
+        Some(metric)
+    }
+}
+
+impl<N: ArrayLength<u8>> TextLayout for EmbedTextLayout<N> {
+    fn width(&self) -> f64 {
+        ////self.font.text_extents(&self.text).x_advance
+        self.lines()  ////
+            .map(|line| self.font.width(self.visible_text(&line)))  ////
+            .fold(0., f64::max)  ////
+    }
+
+    fn height(&self) -> f64 {
+        self.lines()
+            .last()
+            .map_or(0., |line| line.y_offset + line.height)
+    }
+
+    fn ascent(&self) -> f64 {
+        self.font.ascent()
+    }
 
-        // Already checked that text_position > 0 and text_position < count.
-        // If text position is not at a grapheme boundary, use the text position of current
-        // grapheme cluster. But return the original text position
-        // Use the indices (byte offset, which for our purposes = utf8 code units).
-        let grapheme_indices = UnicodeSegmentation::grapheme_indices(self.text.as_str(), true)
-            .take_while(|(byte_idx, _s)| text_position >= *byte_idx);
+    fn descent(&self) -> f64 {
+        self.font.descent()
+    }
 
-        if let Some((byte_idx, _s)) = grapheme_indices.last() {
-            let point_x = self.font.text_extents(&self.text[0..byte_idx]).x_advance;
+    fn line_height(&self) -> f64 {
+        self.font.line_height()
+    }
 
-            Some(HitTestTextPosition {
-                point: Point { x: point_x, y: 0.0 },
-                metrics: HitTestMetrics {
-                    text_position: text_position,
-                },
+    fn image_bounds(&self) -> Rect {
+        //  Add the bounds of the glyphs on each line, moved to the start of the baseline of the line
+        self.lines()
+            .filter_map(|line| {
+                let baseline = line.y_offset + line.baseline - self.ascent();
+                let start = Vec2::new(self.line_x(&line), baseline);
+                self.font.bounds(self.visible_text(&line)).map(|rect| rect + start)
             })
+            .fold(None, |bounds, rect| Some(bounds.map_or(rect, |bounds: Rect| bounds.union(rect))))
+            .unwrap_or(Rect::ZERO)
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines().count()
+    }
+
+    fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
+        self.lines().nth(line_number)
+    }
+
+    fn line_text(&self, line_number: usize) -> Option<&str> {
+        self.line_metric(line_number)
+            .map(|line| &self.text[line.start_offset..line.end_offset])
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        //  Hit test the line at the y coordinate, without its trailing whitespace
+        let (line, y_inside) = self.line_at(point.y);
+        let point = Point::new(point.x - self.line_x(&line), point.y);
+        let mut hit = hit_test_line_point(&self.font, self.visible_text(&line), point);
+        hit.metrics.text_position += line.start_offset;
+        hit.is_inside &= y_inside;
+        hit
+    }
+
+    fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
+        //  Hit test the line that contains the text position
+        let line = self.lines()
+            .take_while(|line| line.start_offset <= text_position)
+            .last()?;
+        let text = &self.text[line.start_offset..line.end_offset];
+        let mut hit = hit_test_line_position(&self.font, text, text_position - line.start_offset)?;
+        hit.point.x += self.line_x(&line);
+        hit.point.y = line.y_offset + line.baseline - self.ascent();
+        hit.metrics.text_position += line.start_offset;
+        Some(hit)
+    }
+}
+
+/// Hit test the point against a line of text, ignoring the `y` coordinate
+////fn hit_test_point(&self, point: Point) -> HitTestPoint {
+fn hit_test_line_point(font: &EmbedFont, text: &str, point: Point) -> HitTestPoint { ////
+    // internal logic is using grapheme clusters, but return the text position associated
+    // with the border of the grapheme cluster.
+
+    // null case
+    if text.is_empty() {  ////
+        return HitTestPoint::default();
+    }
+
+    // get bounds
+    // TODO handle if string is not null yet count is 0?
+    let end = UnicodeSegmentation::graphemes(text, true).count() - 1;
+    let end_bounds = match get_grapheme_boundaries(font, text, end) {
+        Some(bounds) => bounds,
+        None => return HitTestPoint::default(),
+    };
+
+    let start = 0;
+    let start_bounds = match get_grapheme_boundaries(font, text, start) {
+        Some(bounds) => bounds,
+        None => return HitTestPoint::default(),
+    };
+
+    // first test beyond ends
+    if point.x > end_bounds.trailing {
+        let mut res = HitTestPoint::default();
+        res.metrics.text_position = text.len();
+        return res;
+    }
+    if point.x <= start_bounds.leading {
+        return HitTestPoint::default();
+    }
+
+    // then test the beginning and end (common cases)
+    if let Some(hit) = point_x_in_grapheme(point.x, &start_bounds) {
+        return hit;
+    }
+    if let Some(hit) = point_x_in_grapheme(point.x, &end_bounds) {
+        return hit;
+    }
+
+    // Now that we know it's not beginning or end, begin binary search.
+    // Iterative style
+    let mut left = start;
+    let mut right = end;
+    loop {
+        // pick halfway point
+        let middle = left + ((right - left) / 2);
+
+        let grapheme_bounds = match get_grapheme_boundaries(font, text, middle) {
+            Some(bounds) => bounds,
+            None => return HitTestPoint::default(),
+        };
+
+        if let Some(hit) = point_x_in_grapheme(point.x, &grapheme_bounds) {
+            return hit;
+        }
+
+        // since it's not a hit, check if closer to start or finish
+        // and move the appropriate search boundary
+        if point.x < grapheme_bounds.leading {
+            right = middle;
+        } else if point.x > grapheme_bounds.trailing {
+            left = middle + 1;
         } else {
-            // iterated to end boundary
-            Some(HitTestTextPosition {
-                point: Point {
-                    x: self.font.text_extents(&self.text).x_advance,
-                    y: 0.0,
-                },
-                metrics: HitTestMetrics {
-                    text_position: text_len,
-                },
-            })
+            unreachable!("hit_test_point conditional is exhaustive");
         }
-        */ ////
     }
-}
\ No newline at end of file
+}
+
+/// Hit test the text position against a line of text, on the baseline of the line
+////fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition> {
+pub(crate) fn hit_test_line_position(font: &EmbedFont, text: &str, text_position: usize) -> Option<HitTestTextPosition> { ////
+    // Using substrings, but now with unicode grapheme awareness
+
+    let text_len = text.len();
+
+    if text_position == 0 {
+        return Some(HitTestTextPosition::default());
+    }
+
+    if text_position >= text_len {  ////
+        return Some(HitTestTextPosition {
+            point: Point {
+                ////x: self.font.text_extents(&self.text).x_advance,
+                x: font.width(text),  ////
+                y: 0.0,
+            },
+            metrics: HitTestMetrics {
+                text_position: text_len,
+            },
+        });
+    }
+
+    // Already checked that text_position > 0 and text_position < count.
+    // If text position is not at a grapheme boundary, use the text position of current
+    // grapheme cluster. But return the original text position
+    // Use the indices (byte offset, which for our purposes = utf8 code units).
+    let grapheme_indices = UnicodeSegmentation::grapheme_indices(text, true)
+        .take_while(|(byte_idx, _s)| text_position >= *byte_idx);
+
+    if let Some((byte_idx, _s)) = grapheme_indices.last() {
+        ////let point_x = self.font.text_extents(&self.text[0..byte_idx]).x_advance;
+        let point_x = font.position(text, byte_idx);  ////
+
+        Some(HitTestTextPosition {
+            point: Point { x: point_x, y: 0.0 },
+            metrics: HitTestMetrics {
+                text_position,  ////
+            },
+        })
+    } else {
+        // iterated to end boundary
+        Some(HitTestTextPosition {
+            point: Point {
+                ////x: self.font.text_extents(&self.text).x_advance,
+                x: font.width(text),  ////
+                y: 0.0,
+            },
+            metrics: HitTestMetrics {
+                text_position: text_len,
+            },
+        })
+    }
+}
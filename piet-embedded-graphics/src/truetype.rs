@@ -0,0 +1,260 @@
+//! TrueType outline fonts, parsed from a `&'static [u8]` font blob without dynamic memory allocation.
+//! Each glyph is outlined from the font data when it's rendered, so no glyphs are cached.
+use core::str::Chars;
+use piet::kurbo::{ Affine, PathEl, Point, Rect };
+use piet::Error;
+use ttf_parser::{ GlyphId, OutlineBuilder };
+use crate::{ path, raster };
+
+/// TrueType or OpenType font, registered under a name. The font is registered as a `&'static`
+/// reference, because the parsed font tables are too large to be copied into each `EmbedFont`.
+pub struct OutlineFont {
+    /// Name for selecting the font in `new_font_by_name()`
+    name: &'static str,
+    /// Font tables parsed from the font data
+    face: ttf_parser::Font<'static>,
+}
+
+/// Outline font scaled to a size in pixels
+#[derive(Clone, Copy)]
+pub struct ScaledFont {
+    /// Registered font
+    font: &'static OutlineFont,
+    /// Size of the em square in pixels
+    size: f64,
+}
+
+impl OutlineFont {
+    /// Create an outline font with the name, from the font data in a TrueType (`.ttf`) or OpenType (`.otf`) file.
+    ///
+    /// Returns an error if the font data can't be parsed.
+    pub fn new(name: &'static str, data: &'static [u8]) -> Result<OutlineFont, Error> {
+        let face = ttf_parser::Font::from_data(data, 0)
+            .ok_or(Error {})?;  //  Invalid font data
+        face.units_per_em()
+            .ok_or(Error {})?;  //  Invalid em square
+        Ok(OutlineFont { name, face })
+    }
+
+    /// Return the font scaled so that the em square is `size` pixels
+    pub fn with_size(&'static self, size: f64) -> ScaledFont {
+        ScaledFont { font: self, size }
+    }
+
+    /// Name of the font
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl ScaledFont {
+    /// Name of the font
+    pub fn name(&self) -> &'static str {
+        self.font.name
+    }
+
+    /// Size of the em square in pixels
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    /// Distance in pixels from the top of the line to the baseline
+    pub fn ascent(&self) -> f64 {
+        self.font.face.ascender() as f64 * self.scale()
+    }
+
+    /// Distance in pixels from the baseline to the bottom of the line
+    pub fn descent(&self) -> f64 {
+        -self.font.face.descender() as f64 * self.scale()
+    }
+
+    /// Distance in pixels from the top of one line to the top of the next line
+    pub fn line_height(&self) -> f64 {
+        let face = &self.font.face;
+        (face.ascender() as f64 - face.descender() as f64 + face.line_gap() as f64) * self.scale()
+    }
+
+    /// Width in pixels of the text, including the kerning between glyphs
+    pub fn width(&self, text: &str) -> f64 {
+        self.position(text, text.len())
+    }
+
+    /// Horizontal position in pixels of the glyph at the byte index of the text, kerned with the previous glyph.
+    /// At the end of the text, this is the width of the text.
+    pub fn position(&self, text: &str, index: usize) -> f64 {
+        let mut glyphs = self.glyphs(&text[..index]);
+        for _ in &mut glyphs {}
+        match (glyphs.prev, text[index..].chars().next()) {
+            (Some(prev), Some(c)) => glyphs.x + self.kerning(prev, self.glyph(c)) * self.scale(),
+            _ => glyphs.x,
+        }
+    }
+
+    /// Return the glyphs of the text, with the horizontal position of each glyph in pixels
+    pub fn glyphs<'f, 't>(&'f self, text: &'t str) -> Glyphs<'f, 't> {
+        Glyphs { font: self, chars: text.chars(), prev: None, x: 0. }
+    }
+
+    /// Bounding box of the glyph in pixels, with the baseline at the origin and y pointing down,
+    /// or `None` if the glyph has no outline
+    pub fn glyph_bounds(&self, glyph: GlyphId) -> Option<Rect> {
+        let bbox = self.font.face.glyph_bounding_box(glyph)?;
+        let scale = self.scale();
+        Some(Rect::new(
+            bbox.x_min as f64 * scale, -bbox.y_max as f64 * scale,
+            bbox.x_max as f64 * scale, -bbox.y_min as f64 * scale,
+        ))
+    }
+
+    /// Return the outline of the glyph, transformed from glyph coordinates (in pixels, with the
+    /// baseline at the origin and y pointing down) to pixel coordinates
+    pub fn outline(&self, glyph: GlyphId, transform: Affine, tolerance: f64) -> GlyphOutline<'_> {
+        let scale = self.scale();
+        GlyphOutline {
+            face:      &self.font.face,
+            glyph,
+            transform: transform * Affine::new([scale, 0., 0., -scale, 0., 0.]),
+            tolerance,
+        }
+    }
+
+    /// Number of pixels per font unit
+    fn scale(&self) -> f64 {
+        self.size / self.font.face.units_per_em().unwrap_or(1) as f64
+    }
+
+    /// Glyph for the character. Missing glyphs are rendered as glyph 0.
+    fn glyph(&self, c: char) -> GlyphId {
+        self.font.face.glyph_index(c).unwrap_or(GlyphId(0))
+    }
+
+    /// Advance width of the glyph, in font units
+    fn advance(&self, glyph: GlyphId) -> f64 {
+        self.font.face.glyph_hor_advance(glyph).unwrap_or(0) as f64
+    }
+
+    /// Kerning between the pair of glyphs, in font units. The horizontal kerning subtables are added.
+    fn kerning(&self, left: GlyphId, right: GlyphId) -> f64 {
+        self.font.face.kerning_subtables()
+            .filter(|table| table.is_horizontal() && !table.is_variable() && !table.has_cross_stream())
+            .filter_map(|table| table.glyphs_kerning(left, right))
+            .map(|kerning| kerning as f64)
+            .sum()
+    }
+}
+
+/// Iterator over the glyphs of a text and their horizontal positions in pixels.
+/// Glyphs are advanced by their advance widths and kerned in pairs.
+pub struct Glyphs<'f, 't> {
+    /// Font of the glyphs
+    font:  &'f ScaledFont,
+    /// Characters that remain
+    chars: Chars<'t>,
+    /// Previous glyph, for kerning
+    prev:  Option<GlyphId>,
+    /// Position of the next glyph
+    x:     f64,
+}
+
+impl<'f, 't> Iterator for Glyphs<'f, 't> {
+    type Item = (GlyphId, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = self.chars.next()?;
+        let font = self.font;
+        let glyph = font.glyph(c);
+        if let Some(prev) = self.prev {
+            self.x += font.kerning(prev, glyph) * font.scale();
+        }
+        let x = self.x;
+        self.x += font.advance(glyph) * font.scale();
+        self.prev = Some(glyph);
+        Some((glyph, x))
+    }
+}
+
+/// Outline of a glyph, flattened and transformed to pixel coordinates. The glyph is outlined
+/// from the font data each time the segments are requested.
+pub struct GlyphOutline<'f> {
+    /// Font tables that define the glyph
+    face:      &'f ttf_parser::Font<'static>,
+    /// Glyph to be outlined
+    glyph:     GlyphId,
+    /// Transform from font units to pixel coordinates
+    transform: Affine,
+    /// Max distance in pixels between the curves and the line segments
+    tolerance: f64,
+}
+
+impl<'f> raster::Outline for GlyphOutline<'f> {
+    fn for_each_line<F: FnMut(Point, Point)>(&self, f: F) {
+        let mut builder = LineBuilder { f, transform: self.transform, tolerance: self.tolerance, start: None, last: Point::ZERO };
+        self.face.outline_glyph(self.glyph, &mut builder);
+        builder.close();
+    }
+}
+
+/// Receives the contours of a glyph and passes the flattened line segments to `f`. Each contour is closed.
+struct LineBuilder<F: FnMut(Point, Point)> {
+    /// Callback for each line segment
+    f:         F,
+    /// Transform from font units to pixel coordinates
+    transform: Affine,
+    /// Max distance in pixels between the curves and the line segments
+    tolerance: f64,
+    /// Start of the current contour
+    start:     Option<Point>,
+    /// Last point of the current contour
+    last:      Point,
+}
+
+impl<F: FnMut(Point, Point)> LineBuilder<F> {
+    /// Flatten the curve from the last point (in pixel coordinates) into line segments
+    fn curve(&mut self, el: PathEl) {
+        let f = &mut self.f;
+        let last = &mut self.last;
+        path::flatten([PathEl::MoveTo(*last), el].iter().cloned(), self.tolerance, |el| {
+            if let PathEl::LineTo(p) = el {
+                f(*last, p);
+                *last = p;
+            }
+        });
+    }
+
+    /// Transform the point from font units to pixel coordinates
+    fn point(&self, x: f32, y: f32) -> Point {
+        self.transform * Point::new(x as f64, y as f64)
+    }
+}
+
+impl<F: FnMut(Point, Point)> OutlineBuilder for LineBuilder<F> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        let p = self.point(x, y);
+        self.start = Some(p);
+        self.last = p;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        (self.f)(self.last, p);
+        self.last = p;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let el = PathEl::QuadTo(self.point(x1, y1), self.point(x, y));
+        self.curve(el);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let el = PathEl::CurveTo(self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.curve(el);
+    }
+
+    fn close(&mut self) {
+        if let Some(start) = self.start.take() {
+            (self.f)(self.last, start);
+            self.last = start;
+        }
+    }
+}
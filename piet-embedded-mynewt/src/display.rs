@@ -0,0 +1,155 @@
+use core::fmt::Write;
+use arrayvec::ArrayString;
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+    Drawing,
+};
+use embedded_hal::{
+    self,
+    digital::v2::OutputPin,
+};
+use st7735_lcd::{
+    self,
+    Orientation,
+    ST7735,
+};
+use mynewt::{
+    self,
+    result::*,
+    hw::hal,
+};
+#[cfg(feature = "noblock_spi")]
+use piet_embedded_graphics::batch;
+
+/* From PineTime Smart Watch wiki: https://wiki.pine64.org/index.php/PineTime
+Mynewt SPI port 0 connected to ST7789 display:
+LCD_RS (P0.18)	Clock/data pin (CD)
+LCD_CS (P0.25)	Chip select
+LCD_RESET (P0.26)	Display reset
+LCD_BACKLIGHT_{LOW,MID,HIGH} (P0.14, 22, 23)	Backlight (active low)
+
+- Chip select must be held low while driving the display. It must be high when using other SPI devices on the same bus (such as external flash storage) so that the display controller won't respond to the wrong commands.
+- SPI must be used in mode 3. Mode 0 (the default) won't work.
+- LCD_DISPLAY_* is used to enable the backlight. Set at least one to low to see anything on the screen.
+- Use SPI at 8MHz (the fastest clock available on the nRF52832) because otherwise refreshing will be super slow. */
+
+const DISPLAY_SPI: i32  =  0;  //  Mynewt SPI port 0
+const DISPLAY_CS: i32   = 25;  //  LCD_CS (P0.25): Chip select
+const DISPLAY_DC: i32   = 18;  //  LCD_RS (P0.18): Clock/data pin (CD)
+const DISPLAY_RST: i32  = 26;  //  LCD_RESET (P0.26): Display reset
+const DISPLAY_HIGH: i32 = 23;  //  LCD_BACKLIGHT_{LOW,MID,HIGH} (P0.14, 22, 23): Backlight (active low)
+
+/// SPI settings for ST7789 display controller
+static mut SPI_SETTINGS: hal::hal_spi_settings = hal::hal_spi_settings {
+    data_order: hal::HAL_SPI_MSB_FIRST as u8,
+    data_mode:  hal::HAL_SPI_MODE3 as u8,  //  SPI must be used in mode 3. Mode 0 (the default) won't work.
+    baudrate:   8000,  //  In kHZ. Use SPI at 8MHz (the fastest clock available on the nRF52832) because otherwise refreshing will be super slow.
+    word_size:  hal::HAL_SPI_WORD_SIZE_8BIT as u8,
+};
+
+/// Initialise the display controller and return the display, which may be passed to `EmbedRenderContext::new()`
+pub fn start_display() -> MynewtResult<MynewtDisplay> {
+    //  Create SPI port and GPIO pins
+    let mut spi_port = mynewt::SPI::new();
+    let mut dc_gpio =  mynewt::GPIO::new();
+    let mut rst_gpio = mynewt::GPIO::new();
+
+    //  Init SPI port and GPIO pins
+    spi_port.init(
+        DISPLAY_SPI, //  Mynewt SPI port 0
+        DISPLAY_CS,  //  LCD_CS (P0.25): Chip select
+        unsafe { &mut SPI_SETTINGS }
+    ) ? ;
+    dc_gpio.init(DISPLAY_DC) ? ;    //  LCD_RS (P0.18): Clock/data pin (CD)
+    rst_gpio.init(DISPLAY_RST) ? ;  //  LCD_RESET (P0.26): Display reset
+
+    //  Switch on the backlight
+    let mut backlight_high = mynewt::GPIO::new();
+    backlight_high.init(DISPLAY_HIGH) ? ;  //  LCD_BACKLIGHT_{LOW,MID,HIGH} (P0.14, 22, 23): Backlight (active low)
+    backlight_high.set_low() ? ;
+
+    //  Create display driver
+    let mut display = st7735_lcd::ST7735::new(
+        spi_port,    //  SPI Port
+        dc_gpio,     //  GPIO Pin for DC
+        rst_gpio,    //  GPIO Pin for RST
+        true,        //  Whether the display is RGB (true) or BGR (false)
+        true         //  Whether the colours are inverted (true) or not (false)
+    );
+
+    //  Init display driver
+    let mut delay = mynewt::Delay::new();
+    display.init(&mut delay) ? ;
+    display.set_orientation(&Orientation::Landscape) ? ;
+    Ok(MynewtDisplay {
+        display,
+        _backlight_high: backlight_high,
+    })
+}
+
+/// Display for PineTime: ST7789 display controller on Mynewt SPI port 0.
+/// Pass to `EmbedRenderContext::new()` to render piet graphics to the display.
+pub struct MynewtDisplay {
+    /// Display Driver
+    display: Display,
+    /// GPIO Pin for Display Backlight, kept low to switch on the backlight
+    _backlight_high: mynewt::GPIO,
+}
+
+impl MynewtDisplay {
+    /// Display the touched (X, Y) coordinates
+    pub fn show_touch(&mut self, x: u16, y: u16) -> MynewtResult<()> {
+        //  Format coordinates as text into a fixed-size buffer
+        let mut buf_x = ArrayString::<[u8; 20]>::new();
+        let mut buf_y = ArrayString::<[u8; 20]>::new();
+        write!(&mut buf_x, "  X = {}  ", x)
+            .expect("show touch fail");
+        write!(&mut buf_y, "  Y = {}  ", y)
+            .expect("show touch fail");
+
+        //  Prepare the text for rendering
+        let text_x = fonts::Font12x16::<Rgb565>
+            ::render_str(&buf_x)
+            .stroke(Some(Rgb565::from(( 0xff, 0xff, 0xff ))))  //  White
+            .fill(Some(Rgb565::from((   0x00, 0x00, 0x00 ))))  //  Black
+            .translate(Coord::new(40, 100));
+        let text_y = fonts::Font12x16::<Rgb565>
+            ::render_str(&buf_y)
+            .stroke(Some(Rgb565::from(( 0xff, 0xff, 0xff ))))  //  White
+            .fill(Some(Rgb565::from((   0x00, 0x00, 0x00 ))))  //  Black
+            .translate(Coord::new(40, 130));
+
+        //  Render text to display
+        self.draw(text_x);
+        self.draw(text_y);
+        Ok(())
+    }
+
+    /// Draw the pixel colours to the display at the window from (sx,sy) to (ex,ey)
+    pub fn set_pixels<P: IntoIterator<Item = u16>>(&mut self, sx: u16, sy: u16, ex: u16, ey: u16, colors: P) -> Result <(), ()> {
+        self.display.set_pixels(sx, sy, ex, ey, colors)
+    }
+}
+
+impl Drawing<Rgb565> for MynewtDisplay {
+    /// Draw the item to the display, batched as Pixel Blocks if `noblock_spi` is enabled
+    fn draw<T>(&mut self, item: T)
+    where T: IntoIterator<Item = Pixel<Rgb565>> {
+        #[cfg(not(feature = "noblock_spi"))]  //  If batching is disabled...
+        self.display.draw(item);              //  Draw text or graphics the usual slow way
+
+        #[cfg(feature = "noblock_spi")]       //  If batching is enabled...
+        batch::draw_blocks(                   //  Draw text or graphics the new faster way, as pixel blocks
+            item,
+            |x_left, y_top, x_right, y_bottom, colors|
+                self.display.set_pixels(x_left, y_top, x_right, y_bottom, colors.iter().cloned())
+        ).expect("draw blocks fail");
+    }
+}
+
+/// Display Driver
+type Display = ST7735<mynewt::SPI, mynewt::GPIO, mynewt::GPIO>;
+
+//  `test_display()` has been moved to `pinetime-rust-mynewt/rust/app/src/display.rs`
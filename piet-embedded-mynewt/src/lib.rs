@@ -0,0 +1,12 @@
+//! Display driver for PineTime: ST7789 display controller on Mynewt SPI port 0.
+//!
+//! `start_display()` returns a `MynewtDisplay` that may be passed to
+//! `piet_embedded_graphics::EmbedRenderContext::new()`. This crate is kept apart from
+//! `piet-embedded-graphics` because it needs the `mynewt` and `st7735-lcd-batch-rs` checkouts
+//! next to this repository.
+
+#![no_std]
+
+mod display;
+
+pub use display::{ start_display, MynewtDisplay };
@@ -1,5 +1,7 @@
 //! A wide assortment of graphics meant to show off many different uses of piet
 
+use std::sync::OnceLock; ////
+
 use piet::kurbo::{Affine, BezPath, Line, Point, Rect, RoundedRect, Vec2};
 
 use piet::{
@@ -40,8 +42,12 @@ pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
         Ok(())
     })?;
 
-    let image_data = make_image_data(256, 256);
-    let image = rc.make_image(256, 256, &image_data, ImageFormat::RgbaSeparate)?;
+    // Images borrow `'static` data, so the image is made once and kept in a static ////
+    static IMAGE_DATA: OnceLock<Vec<u8>> = OnceLock::new(); ////
+    let image_data = IMAGE_DATA.get_or_init(|| make_image_data(256, 256)); ////
+    ////let image_data = make_image_data(256, 256);
+    let image = rc.make_image(256, 256, image_data, ImageFormat::RgbaSeparate)?; ////
+    ////let image = rc.make_image(256, 256, &image_data, ImageFormat::RgbaSeparate)?;
     rc.draw_image(
         &image,
         Rect::new(150.0, 50.0, 180.0, 80.0),
@@ -1,9 +1,13 @@
 //! A bunch of image test cases.
 
+use std::sync::OnceLock; ////
+
 use piet::kurbo::Rect;
 use piet::{Color, Error, ImageFormat, InterpolationMode, RenderContext};
 
 pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    // Images borrow `'static` data, so the images are made once and kept in a static ////
+    static IMAGE_DATA: [OnceLock<Vec<u8>>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()]; ////
     rc.clear(Color::WHITE);
 
     let mut y = 5.0;
@@ -12,13 +16,15 @@ pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
         InterpolationMode::Bilinear,
     ] {
         let mut x = 5.0;
-        for &format in &[
+        for (i, &format) in [ ////
             ImageFormat::RgbaSeparate,
             ImageFormat::RgbaPremul,
             ImageFormat::Rgb,
-        ] {
-            let image_data = make_image_data(16, 16, format);
-            let image = rc.make_image(16, 16, &image_data, format)?;
+        ].iter().enumerate() { ////
+            let image_data = IMAGE_DATA[i].get_or_init(|| make_image_data(16, 16, format)); ////
+            ////let image_data = make_image_data(16, 16, format);
+            let image = rc.make_image(16, 16, image_data, format)?; ////
+            ////let image = rc.make_image(16, 16, &image_data, format)?;
             rc.draw_image(&image, Rect::new(x, y, x + 40.0, y + 40.0), mode);
             x += 50.0;
         }
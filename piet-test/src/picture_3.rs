@@ -36,7 +36,8 @@ pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
     for i in 0..8 {
         let mut style = StrokeStyle::new();
         dashes.push((i + 1) as f64);
-        style.set_dash(dashes.clone(), 0.0);
+        style.set_dash(dashes.iter().cloned().collect(), 0.0); ////
+        ////style.set_dash(dashes.clone(), 0.0);
         rc.stroke_styled(Line::new((x, y), (x + 50.0, y)), &brush, 2.0, &style);
         y += 10.0;
     }
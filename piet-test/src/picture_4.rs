@@ -23,7 +23,7 @@ pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
         center: Point::new(30.0, 30.0),
         origin_offset: Vec2::new(10.0, 10.0),
         radius: 30.0,
-        stops,
+        stops: stops.into_iter().collect(), ////
     }))?;
     rc.fill(Rect::new(0.0, 0.0, 60.0, 60.0), &gradient);
     let stops2 = vec![
@@ -39,8 +39,26 @@ pub fn draw<R: RenderContext>(rc: &mut R) -> Result<(), Error> {
     let gradient2 = rc.gradient(FixedGradient::Linear(FixedLinearGradient {
         start: Point::new(0.0, 0.0),
         end: Point::new(60.0, 0.0),
-        stops: stops2,
+        stops: stops2.into_iter().collect(), ////
     }))?;
     rc.fill(Rect::new(0.0, 80.0, 60.0, 100.0), &gradient2);
+    // The focal point is outside the circle, so only the cone of circles is painted.
+    let stops3 = vec![ ////
+        GradientStop { ////
+            pos: 0.0, ////
+            color: Color::WHITE, ////
+        }, ////
+        GradientStop { ////
+            pos: 1.0, ////
+            color: Color::BLACK, ////
+        }, ////
+    ]; ////
+    let gradient3 = rc.gradient(FixedGradient::Radial(FixedRadialGradient { ////
+        center: Point::new(60.0, 130.0), ////
+        origin_offset: Vec2::new(-40.0, 0.0), ////
+        radius: 10.0, ////
+        stops: stops3.into_iter().collect(), ////
+    }))?; ////
+    rc.fill(Rect::new(0.0, 110.0, 80.0, 150.0), &gradient3); ////
     Ok(())
 }
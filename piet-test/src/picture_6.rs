@@ -0,0 +1,45 @@
+//! Fill rules: non-zero on the left, even-odd on the right.
+
+use piet::kurbo::{BezPath, Circle, Point, Shape, Vec2};
+
+use piet::{Color, Error, RenderContext};
+
+pub fn draw(rc: &mut impl RenderContext) -> Result<(), Error> {
+    rc.clear(Color::WHITE);
+    let brush = rc.solid_brush(Color::rgb8(0x00, 0x00, 0x80));
+
+    // A self-intersecting star: the centre is filled by non-zero but not by even-odd.
+    rc.fill(pentagram(Point::new(40.0, 40.0), 30.0), &brush);
+    rc.fill_even_odd(pentagram(Point::new(120.0, 40.0), 30.0), &brush);
+
+    // Two circles with the same orientation: even-odd leaves a hole.
+    rc.fill(ring(Point::new(40.0, 120.0), 30.0, 15.0), &brush);
+    rc.fill_even_odd(ring(Point::new(120.0, 120.0), 30.0, 15.0), &brush);
+    Ok(())
+}
+
+fn pentagram(center: Point, radius: f64) -> BezPath {
+    let mut result = BezPath::new();
+    for i in 0..5 {
+        let th = std::f64::consts::PI * (0.8 * (i as f64) - 0.5);
+        let pt = center + radius * Vec2::from_angle(th);
+        if i == 0 {
+            result.move_to(pt);
+        } else {
+            result.line_to(pt);
+        }
+    }
+    result.close_path();
+    result
+}
+
+fn ring(center: Point, outer: f64, inner: f64) -> BezPath {
+    let mut result = BezPath::new();
+    for el in Circle::new(center, outer).to_bez_path(0.1) {
+        result.push(el);
+    }
+    for el in Circle::new(center, inner).to_bez_path(0.1) {
+        result.push(el);
+    }
+    result
+}
@@ -546,6 +546,10 @@ impl TextLayout for WebTextLayout {
             .expect("Text measurement failed")
     }
 
+    fn line_text(&self, line_number: usize) -> Option<&str> { ////
+        if line_number == 0 { Some(&self.text) } else { None } ////
+    } ////
+
     // first assume one line.
     // TODO do with lines
     fn hit_test_point(&self, point: Point) -> HitTestPoint {
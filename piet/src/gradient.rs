@@ -30,7 +30,7 @@ use arrayvec::ArrayVec;  ////
 
 use kurbo::{Point, Rect, Size, Vec2};
 
-////use crate::{IntoBrush, RenderContext};
+use crate::{IntoBrush, RenderContext};
 
 use crate::Color;
 
@@ -334,7 +334,6 @@ impl LinearGradient {
     /// onto points in `rect`.
     ///
     /// [`FixedLinearGradient`]: struct.FixedLinearGradient.html
-    #[allow(dead_code)] ////
     fn resolve(&self, rect: Rect) -> FixedLinearGradient {
         FixedLinearGradient {
             start: self.start.resolve(rect),
@@ -398,7 +397,6 @@ impl RadialGradient {
     /// onto points in `rect`.
     ///
     /// [`FixedRadialGradient`]: struct.FixedRadialGradient.html
-    #[allow(dead_code)] ////
     fn resolve(&self, rect: Rect) -> FixedRadialGradient {
         let scale_len = match self.scale_mode {
             ScaleMode::Fill => rect.width().max(rect.height()),
@@ -431,43 +429,40 @@ impl From<FixedRadialGradient> for FixedGradient {
     }
 }
 
-/* ////
-    impl<P: RenderContext> IntoBrush<P> for FixedGradient {
-        fn make_brush<'a>(&'a self, piet: &mut P, _bbox: impl FnOnce() -> Rect) -> P::Brush { ////
-        ////fn make_brush<'a>(&'a self, piet: &mut P, _bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
-            // Also, at some point we might want to be smarter about the extra clone here.
-            ////Bow::Owned(
-                piet.gradient(self.clone())
-                ////piet.gradient(self.to_owned())
-                    .expect("error creating gradient")
-            ////)
-        }
+impl<P: RenderContext> IntoBrush<P> for FixedGradient {
+    fn make_brush<'a>(&'a self, piet: &mut P, _bbox: impl FnOnce() -> Rect) -> P::Brush { ////
+    ////fn make_brush<'a>(&'a self, piet: &mut P, _bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
+        // Also, at some point we might want to be smarter about the extra clone here.
+        piet.gradient(self.clone()).expect("error creating gradient") ////
+        ////Bow::Owned(
+            ////piet.gradient(self.to_owned())
+                ////.expect("error creating gradient")
+        ////)
     }
+}
 
-    impl<P: RenderContext> IntoBrush<P> for LinearGradient {
-        fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> P::Brush { ////
-        ////fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
-            let rect = bbox();
-            let gradient = self.resolve(rect);
-            // Perhaps the make_brush method should be fallible instead of panicking.
-            piet.gradient(gradient).expect("error creating gradient") ////
-            ////Bow::Owned(piet.gradient(gradient).expect("error creating gradient"))
-        }
+impl<P: RenderContext> IntoBrush<P> for LinearGradient {
+    fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> P::Brush { ////
+    ////fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
+        let rect = bbox();
+        let gradient = self.resolve(rect);
+        // Perhaps the make_brush method should be fallible instead of panicking.
+        piet.gradient(gradient).expect("error creating gradient") ////
+        ////Bow::Owned(piet.gradient(gradient).expect("error creating gradient"))
     }
+}
 
-    impl<P: RenderContext> IntoBrush<P> for RadialGradient {
-        fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> P::Brush { ////
-        ////fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
-            let rect = bbox();
-            let gradient = self.resolve(rect);
-            // Perhaps the make_brush method should be fallible instead of panicking.
-            piet.gradient(gradient).expect("error creating gradient") ////
-            ////Bow::Owned(piet.gradient(gradient).expect("error creating gradient"))
-        }
+impl<P: RenderContext> IntoBrush<P> for RadialGradient {
+    fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> P::Brush { ////
+    ////fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
+        let rect = bbox();
+        let gradient = self.resolve(rect);
+        // Perhaps the make_brush method should be fallible instead of panicking.
+        piet.gradient(gradient).expect("error creating gradient") ////
+        ////Bow::Owned(piet.gradient(gradient).expect("error creating gradient"))
     }
-*/ ////
+}
 
-#[allow(dead_code)] ////
 fn equalize_sides_preserving_center(rect: Rect, new_len: f64) -> Rect {
     let size = Size::new(new_len, new_len);
     let origin = rect.center() - size.to_vec2() / 2.;
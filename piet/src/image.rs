@@ -0,0 +1,66 @@
+//! Bitmap images that borrow their pixels, so that images may be rendered without dynamic memory allocation.
+
+use crate::{Error, ImageFormat};
+
+/// A bitmap image whose pixels are borrowed from a buffer, like a `&'static [u8]` in flash
+/// or a buffer supplied by the caller. The pixels are stored row by row, and each row starts
+/// on a new byte.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageBuf<'a> {
+    width: usize,
+    height: usize,
+    format: ImageFormat,
+    pixels: &'a [u8],
+}
+
+impl<'a> ImageBuf<'a> {
+    /// Create an image that borrows the pixels in the buffer.
+    ///
+    /// Returns an error if the buffer is too small for the width, height and format,
+    /// or if the format is unknown.
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: &'a [u8],
+        format: ImageFormat,
+    ) -> Result<ImageBuf<'a>, Error> {
+        let size = width
+            .checked_mul(format.bits_per_pixel())
+            .and_then(|bits| height.checked_mul((bits + 7) / 8));
+        match size {
+            Some(size) if size > 0 && size <= pixels.len() => Ok(ImageBuf {
+                width,
+                height,
+                format,
+                pixels: &pixels[..size],
+            }),
+            _ => Err(Error {}),
+        }
+    }
+
+    /// The width of the image, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the image, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixel format of the image.
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// The pixels of the image, row by row.
+    pub fn pixels(&self) -> &'a [u8] {
+        self.pixels
+    }
+
+    /// The pixels of row `y`.
+    pub fn row(&self, y: usize) -> &'a [u8] {
+        let bytes_per_row = self.format.bytes_per_row(self.width);
+        &self.pixels[y * bytes_per_row..(y + 1) * bytes_per_row]
+    }
+}
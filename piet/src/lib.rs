@@ -7,6 +7,7 @@ mod color;
 mod conv;
 mod error;
 mod gradient;
+mod image; ////
 mod null_renderer;
 mod render_context;
 mod shapes;
@@ -16,6 +17,7 @@ pub use crate::color::*;
 pub use crate::conv::*;
 pub use crate::error::*;
 pub use crate::gradient::*;
+pub use crate::image::*; ////
 pub use crate::null_renderer::*;
 pub use crate::render_context::*;
 pub use crate::shapes::*;
@@ -6,9 +6,9 @@
 use kurbo::{Affine, Point, Rect, Shape};
 
 use crate::{
-    Color, Error, ////FixedGradient, 
-    Font, FontBuilder, HitTestPoint, HitTestTextPosition, ////ImageFormat,
-    ////InterpolationMode, 
+    Color, Error, FixedGradient, 
+    Font, FontBuilder, HitTestPoint, HitTestTextPosition, ImageFormat,
+    InterpolationMode, 
     IntoBrush, RenderContext, StrokeStyle, Text, TextLayout, TextLayoutBuilder,
 };
 
@@ -47,7 +47,7 @@ impl NullRenderContext {
 
 impl RenderContext for NullRenderContext {
     type Brush = NullBrush;
-    ////type Image = NullImage;
+    type Image = NullImage;
     type Text = NullText;
     type TextLayout = NullTextLayout;
 
@@ -59,11 +59,9 @@ impl RenderContext for NullRenderContext {
         NullBrush
     }
 
-    /* ////
-        fn gradient(&mut self, _gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
-            Ok(NullBrush)
-        }
-    */ ////
+    fn gradient(&mut self, _gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error> {
+        Ok(NullBrush)
+    }
 
     fn clear(&mut self, _color: Color) {}
 
@@ -107,24 +105,23 @@ impl RenderContext for NullRenderContext {
     }
     fn transform(&mut self, _transform: Affine) {}
 
-    /* ////
-        fn make_image(
-            &mut self,
-            _width: usize,
-            _height: usize,
-            _buf: &[u8],
-            _format: ImageFormat,
-        ) -> Result<Self::Image, Error> {
-            Ok(NullImage)
-        }
-        fn draw_image(
-            &mut self,
-            _image: &Self::Image,
-            _rect: impl Into<Rect>,
-            _interp: InterpolationMode,
-        ) {
-        }
-    */ ////
+    fn make_image(
+        &mut self,
+        _width: usize,
+        _height: usize,
+        _buf: &'static [u8], ////
+        ////_buf: &[u8],
+        _format: ImageFormat,
+    ) -> Result<Self::Image, Error> {
+        Ok(NullImage)
+    }
+    fn draw_image(
+        &mut self,
+        _image: &Self::Image,
+        _rect: impl Into<Rect>,
+        _interp: InterpolationMode,
+    ) {
+    }
 }
 
 impl Text for NullText {
@@ -165,6 +162,10 @@ impl TextLayout for NullTextLayout {
         42.0
     }
 
+    fn line_text(&self, line_number: usize) -> Option<&str> { ////
+        if line_number == 0 { Some("") } else { None } ////
+    } ////
+
     fn hit_test_point(&self, _point: Point) -> HitTestPoint {
         HitTestPoint::default()
     }
@@ -6,8 +6,8 @@
 use kurbo::{Affine, Point, Rect, Shape};
 
 use crate::{
-    Color, Error, ////FixedGradient, FixedLinearGradient, FixedRadialGradient, LinearGradient,
-    ////RadialGradient, 
+    Color, Error, FixedGradient, FixedLinearGradient, FixedRadialGradient, LinearGradient,
+    RadialGradient, 
     StrokeStyle, Text, TextLayout,
 };
 
@@ -29,18 +29,54 @@ pub enum ImageFormat {
     RgbaSeparate,
     /// 4 bytes per pixel, in RGBA order, with premultiplied alpha.
     RgbaPremul,
+    /// 2 bytes per pixel, 5 bits red, 6 bits green and 5 bits blue, little endian.
+    Rgb565Le, ////
+    /// 2 bytes per pixel, 5 bits red, 6 bits green and 5 bits blue, big endian.
+    Rgb565Be, ////
+    /// 1 byte per pixel, grey level.
+    Grayscale8, ////
+    /// 1 byte per pixel, alpha only. The image is always painted black with this alpha,
+    /// because images aren't tinted by a brush. To draw a coloured mask, use `RgbaSeparate`
+    /// or `Indexed8` with the colour instead.
+    Alpha8, ////
+    /// 1 byte per pixel, an index into the palette of Rgb565 colours.
+    Indexed8(&'static [u16]), ////
+    /// 4 bits per pixel, an index into the palette of Rgb565 colours.
+    /// The first pixel is in the high 4 bits of each byte, and each row starts on a new byte.
+    Indexed4(&'static [u16]), ////
     /// More formats may be added later.
     #[doc(hidden)]
     _NonExhaustive,
 }
 
 impl ImageFormat {
+    /// The number of bytes per pixel, rounded up to whole bytes. Unknown formats have 0 bytes.
     pub fn bytes_per_pixel(&self) -> usize {
+        (self.bits_per_pixel() + 7) / 8 ////
+        /* ////
         match *self {
             ImageFormat::Rgb => 3,
             ImageFormat::RgbaPremul | ImageFormat::RgbaSeparate => 4,
             _ => panic!(),
         }
+        */ ////
+    }
+
+    /// The number of bits per pixel. Unknown formats have 0 bits.
+    pub fn bits_per_pixel(&self) -> usize { ////
+        match *self {
+            ImageFormat::Rgb => 24,
+            ImageFormat::RgbaPremul | ImageFormat::RgbaSeparate => 32,
+            ImageFormat::Rgb565Le | ImageFormat::Rgb565Be => 16,
+            ImageFormat::Grayscale8 | ImageFormat::Alpha8 | ImageFormat::Indexed8(_) => 8,
+            ImageFormat::Indexed4(_) => 4,
+            ImageFormat::_NonExhaustive => 0,
+        }
+    }
+
+    /// The number of bytes in each row of `width` pixels, rounded up to whole bytes.
+    pub fn bytes_per_row(&self, width: usize) -> usize { ////
+        (width * self.bits_per_pixel() + 7) / 8
     }
 }
 
@@ -68,8 +104,8 @@ where
     type Text: Text<TextLayout = Self::TextLayout>;
     type TextLayout: TextLayout;
 
-    /////// The associated type of an image.
-    ////type Image;
+    /// The associated type of an image.
+    type Image;
 
     /// Report an internal error.
     ///
@@ -87,8 +123,8 @@ where
     /// other potentially retained objects will be heavier.
     fn solid_brush(&mut self, color: Color) -> Self::Brush;
 
-    /////// Create a new gradient brush.
-    ////fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error>;
+    /// Create a new gradient brush.
+    fn gradient(&mut self, gradient: impl Into<FixedGradient>) -> Result<Self::Brush, Error>;
 
     /// Clear the canvas with the given color.
     ///
@@ -173,13 +209,15 @@ where
     /// until a [`restore`](#method.restore) operation.
     fn transform(&mut self, transform: Affine);
 
-    /* ////
     /// Create a new image from a pixel buffer.
+    ///
+    /// The pixels are borrowed instead of copied, so the buffer must outlive the image.
     fn make_image(
         &mut self,
         width: usize,
         height: usize,
-        buf: &[u8],
+        buf: &'static [u8], ////
+        ////buf: &[u8],
         format: ImageFormat,
     ) -> Result<Self::Image, Error>;
 
@@ -188,7 +226,6 @@ where
     /// The image is scaled to the provided `rect`. It will be squashed if
     /// aspect ratios don't match.
     fn draw_image(&mut self, image: &Self::Image, rect: impl Into<Rect>, interp: InterpolationMode);
-    */ ////
 }
 
 /// A trait for various types that can be used as brushes. These include
@@ -245,9 +282,9 @@ impl<P: RenderContext> IntoBrush<P> for Color {
 ////#[derive(Debug, Clone)]
 pub enum PaintBrush {
     Color(Color),
-    ////Linear(LinearGradient),
-    ////Radial(RadialGradient),
-    ////Fixed(FixedGradient),
+    Linear(LinearGradient),
+    Radial(RadialGradient),
+    Fixed(FixedGradient),
 }
 
 impl<P: RenderContext> IntoBrush<P> for PaintBrush {
@@ -255,9 +292,9 @@ impl<P: RenderContext> IntoBrush<P> for PaintBrush {
     ////fn make_brush<'a>(&'a self, piet: &mut P, bbox: impl FnOnce() -> Rect) -> Bow<'a, P::Brush> {
         match self {
             PaintBrush::Color(color) => color.make_brush(piet, bbox),
-            ////PaintBrush::Linear(linear) => linear.make_brush(piet, bbox),
-            ////PaintBrush::Radial(radial) => radial.make_brush(piet, bbox),
-            ////PaintBrush::Fixed(fixed) => fixed.make_brush(piet, bbox),
+            PaintBrush::Linear(linear) => linear.make_brush(piet, bbox),
+            PaintBrush::Radial(radial) => radial.make_brush(piet, bbox),
+            PaintBrush::Fixed(fixed) => fixed.make_brush(piet, bbox),
         }
     }
 }
@@ -268,34 +305,32 @@ impl From<Color> for PaintBrush {
     }
 }
 
-/* ////
-    impl From<LinearGradient> for PaintBrush {
-        fn from(src: LinearGradient) -> PaintBrush {
-            PaintBrush::Linear(src)
-        }
+impl From<LinearGradient> for PaintBrush {
+    fn from(src: LinearGradient) -> PaintBrush {
+        PaintBrush::Linear(src)
     }
+}
 
-    impl From<RadialGradient> for PaintBrush {
-        fn from(src: RadialGradient) -> PaintBrush {
-            PaintBrush::Radial(src)
-        }
+impl From<RadialGradient> for PaintBrush {
+    fn from(src: RadialGradient) -> PaintBrush {
+        PaintBrush::Radial(src)
     }
+}
 
-    impl From<FixedGradient> for PaintBrush {
-        fn from(src: FixedGradient) -> PaintBrush {
-            PaintBrush::Fixed(src)
-        }
+impl From<FixedGradient> for PaintBrush {
+    fn from(src: FixedGradient) -> PaintBrush {
+        PaintBrush::Fixed(src)
     }
+}
 
-    impl From<FixedLinearGradient> for PaintBrush {
-        fn from(src: FixedLinearGradient) -> PaintBrush {
-            PaintBrush::Fixed(src.into())
-        }
+impl From<FixedLinearGradient> for PaintBrush {
+    fn from(src: FixedLinearGradient) -> PaintBrush {
+        PaintBrush::Fixed(src.into())
     }
+}
 
-    impl From<FixedRadialGradient> for PaintBrush {
-        fn from(src: FixedRadialGradient) -> PaintBrush {
-            PaintBrush::Fixed(src.into())
-        }
+impl From<FixedRadialGradient> for PaintBrush {
+    fn from(src: FixedRadialGradient) -> PaintBrush {
+        PaintBrush::Fixed(src.into())
     }
-*/ ////
\ No newline at end of file
+}
@@ -1,6 +1,6 @@
 //! Traits for fonts and text handling.
 
-use crate::kurbo::Point;
+use crate::kurbo::{Point, Rect}; ////
 use crate::Error;
 
 pub trait Text {
@@ -23,12 +23,55 @@ pub trait FontBuilder {
 
 pub trait Font {}
 
-pub trait TextLayoutBuilder {
+pub trait TextLayoutBuilder: Sized { ////
     type Out: TextLayout;
 
+    /// Wrap the text into lines that are no wider than `width`, breaking the lines at
+    /// Unicode line break opportunities. Without a max width, the text is laid out in one line,
+    /// except at hard line breaks like `\n`.
+    ///
+    /// The default implementation ignores the max width, for backends that lay out one line.
+    fn max_width(self, _width: f64) -> Self { ////
+        self ////
+    } ////
+
+    /// Align each line of text within the max width, or within the widest line if there's no max width.
+    /// The default is `TextAlignment::Left`.
+    ///
+    /// The default implementation ignores the alignment, for backends that lay out one line.
+    fn alignment(self, _alignment: TextAlignment) -> Self { ////
+        self ////
+    } ////
+
+    /// If `ellipsis` is true, lay out the text in one line, and if the text doesn't fit in the max width
+    /// or has more than one line, truncate it at a grapheme boundary and end it with an ellipsis.
+    ///
+    /// The default implementation ignores the ellipsis, for backends that lay out one line.
+    fn ellipsis(self, _ellipsis: bool) -> Self { ////
+        self ////
+    } ////
+
     fn build(self) -> Result<Self::Out, Error>;
 }
 
+/// Horizontal alignment of the lines of a text layout, set by
+/// [`TextLayoutBuilder::alignment`](../piet/trait.TextLayoutBuilder.html#tymethod.alignment).
+#[derive(Clone, Copy, Debug, PartialEq)] ////
+pub enum TextAlignment { ////
+    /// Lines start at the left edge.
+    Left, ////
+    /// Lines are centred.
+    Center, ////
+    /// Lines end at the right edge.
+    Right, ////
+} ////
+
+impl Default for TextAlignment { ////
+    fn default() -> Self { ////
+        TextAlignment::Left ////
+    } ////
+} ////
+
 /// # Text Layout
 ///
 /// ## Text Position
@@ -41,11 +84,90 @@ pub trait TextLayoutBuilder {
 /// - If the text position is not at a code point or grapheme boundary, undesirable behavior may
 /// occur.
 ///
+/// ## Lines
+///
+/// The text is wrapped into lines by [`TextLayoutBuilder::max_width`](../piet/trait.TextLayoutBuilder.html#tymethod.max_width).
+/// Lines are numbered from `0`, and each line is described by a [`LineMetric`][].
+///
+/// [`LineMetric`]: struct.LineMetric.html
+///
+/// ## Coordinates
+///
+/// Points are relative to the `pos` of [`draw_text`](../piet/trait.RenderContext.html#tymethod.draw_text),
+/// which is the baseline at the start of the first line. `y` increases downwards, so the top of the
+/// first line is at `-ascent()`.
+///
+/// ## Default implementations
+///
+/// The line and metric methods have default implementations for backends that lay out one line.
+/// They describe a single line that is `width()` wide, with an ascent and descent of `0.0` unless
+/// the backend provides its font metrics.
 pub trait TextLayout {
-    /// Measure the advance width of the text.
+    /// Measure the advance width of the text. For wrapped text, this is the width of the widest line.
     fn width(&self) -> f64;
 
+    /// Measure the total height of the lines of text. The default is `line_height()` for each line.
+    fn height(&self) -> f64 { ////
+        self.line_count() as f64 * self.line_height() ////
+    } ////
+
+    /// Distance from the baseline up to the top of each line. The default is `0.0`.
+    fn ascent(&self) -> f64 { ////
+        0.0 ////
+    } ////
+
+    /// Distance from the baseline down to the bottom of each line, as a positive number. The default is `0.0`.
+    fn descent(&self) -> f64 { ////
+        0.0 ////
+    } ////
+
+    /// Distance from the baseline of each line to the baseline of the next line.
+    /// The default is `ascent() + descent()`.
+    fn line_height(&self) -> f64 { ////
+        self.ascent() + self.descent() ////
+    } ////
+
+    /// Bounding box of the glyphs as they are drawn, relative to the baseline at the start of the first line.
+    /// The default is `width()` wide, from `-ascent()` to `descent()`.
+    fn image_bounds(&self) -> Rect { ////
+        Rect::new(0.0, -self.ascent(), self.width(), self.descent()) ////
+    } ////
+
+    /// Number of lines in the layout. A layout always has at least one line, even if the text is empty.
+    /// The default is `1`.
+    fn line_count(&self) -> usize { ////
+        1 ////
+    } ////
+
+    /// Return the metrics of the line, or `None` if the line number is out of range.
+    /// The default describes line `0` with the text of `line_text(0)`.
+    fn line_metric(&self, line_number: usize) -> Option<LineMetric> { ////
+        if line_number != 0 { ////
+            return None; ////
+        } ////
+        let text = self.line_text(0).unwrap_or(""); ////
+        Some(LineMetric { ////
+            start_offset: 0, ////
+            end_offset: text.len(), ////
+            trailing_whitespace: text.len() - text.trim_end().len(), ////
+            baseline: self.ascent(), ////
+            height: self.line_height(), ////
+            y_offset: 0.0, ////
+        }) ////
+    } ////
+
+    /// Return the text of the line, including any trailing whitespace,
+    /// or `None` if the line number is out of range.
+    ///
+    /// The default is `None`, because the trait can't see the text. Layouts that keep their
+    /// text should return it as line `0`.
+    fn line_text(&self, _line_number: usize) -> Option<&str> { ////
+        None ////
+    } ////
+
     /// Given a `Point`, determine the corresponding text position.
+    /// The line is selected by the `y` coordinate. Points above the first line hit the first line,
+    /// and points below the last line hit the last line.
     ///
     /// ## Return value:
     /// Returns a [`HitTestPoint`][] describing the results of the test.
@@ -68,13 +190,13 @@ pub trait TextLayout {
     fn hit_test_point(&self, point: Point) -> HitTestPoint;
 
     /// Given a text position, determine the corresponding pixel location.
-    /// (currently consider the text layout just one line)
     ///
     /// ## Return value:
     /// Returns a [`HitTestTextPosition`][] describing the results of the test.
     ///
     /// [`HitTestTextPosition`][] field `point` is the point offset of the boundary of the
-    /// grapheme cluster that the text position is a part of.
+    /// grapheme cluster that the text position is a part of. The `y` value is the baseline of the
+    /// line that contains the text position.
     ///
     /// [`HitTestTextPosition`][] field `metrics` is a [`HitTestMetrics`][] struct. [`HitTestMetrics`][] field `text_position` is the original text position (unless out of bounds).
     ///
@@ -94,6 +216,23 @@ pub trait TextLayout {
     fn hit_test_text_position(&self, text_position: usize) -> Option<HitTestTextPosition>;
 }
 
+/// Metrics of a line of text, returned by [`line_metric`](../piet/trait.TextLayout.html#tymethod.line_metric).
+#[derive(Clone, Debug, Default, PartialEq)] ////
+pub struct LineMetric { ////
+    /// Text position of the start of the line.
+    pub start_offset: usize, ////
+    /// Text position of the end of the line, after any trailing whitespace.
+    pub end_offset: usize, ////
+    /// Length in utf-8 code units of the whitespace at the end of the line, including the line break.
+    pub trailing_whitespace: usize, ////
+    /// Distance from the top of the line to the baseline.
+    pub baseline: f64, ////
+    /// Height of the line.
+    pub height: f64, ////
+    /// Distance from the top of the first line to the top of this line.
+    pub y_offset: f64, ////
+} ////
+
 /// return values for [`hit_test_point`](../piet/trait.TextLayout.html#tymethod.hit_test_point).
 #[derive(Debug, Default, PartialEq)]
 pub struct HitTestPoint {